@@ -0,0 +1,37 @@
+//! Offline re-encryption of the stored pastes with the active key, run it after adding a
+//! new key to `PASTEBIN_ENCRYPTION_KEYS` (and keep the old keys listed until it's done).
+use rocket_pastebin::crypto;
+use rocket_pastebin::CustomConfig;
+
+fn main() {
+  let custom_config = CustomConfig::new();
+
+  let keyring = match &custom_config.keyring {
+    Some(keyring) => keyring.clone(),
+    None => {
+      eprintln!("PASTEBIN_ENCRYPTION_KEYS is not set, there is nothing to re-encrypt with.");
+      std::process::exit(1);
+    }
+  };
+
+  // the raw backend, blobs of deduplicated pastes get rewritten in place under their digest
+  let store = match custom_config.storage.open() {
+    Ok(store) => store,
+    Err(e) => {
+      eprintln!("Error while opening the paste store. Error: {}", e);
+      std::process::exit(1);
+    }
+  };
+
+  match crypto::reencrypt(store.as_ref(), &keyring) {
+    Ok(count) => println!(
+      "Re-encrypted {} pastes with key ({})!",
+      count,
+      keyring.active_key_id()
+    ),
+    Err(e) => {
+      eprintln!("Error while re-encrypting the pastes. Error: {}", e);
+      std::process::exit(1);
+    }
+  }
+}
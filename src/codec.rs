@@ -0,0 +1,127 @@
+use crate::store::PasteReader;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{self, Cursor, Read, Write};
+
+/// Every paste written by the service starts with this header, followed by one byte naming
+/// the encoding of the rest of the file. Files without it were written before compression
+/// existed and are served as they are.
+const FRAME_MAGIC: &[u8] = b"PBF1";
+
+const IDENTITY: u8 = 0;
+const GZIP: u8 = 1;
+
+/// A paste on its way out, `content_encoding` is set when `body` is still compressed.
+pub struct Decoded {
+  pub body: PasteReader,
+  pub content_encoding: Option<&'static str>,
+}
+
+/// Frames the paste for storage, gzip-compressing it when `compress` is set and it
+/// actually makes the paste smaller.
+pub fn encode(data: &[u8], compress: bool) -> io::Result<Vec<u8>> {
+  if compress {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 2), Compression::default());
+    encoder.write_all(data)?;
+    let compressed = encoder.finish()?;
+
+    if compressed.len() < data.len() {
+      return Ok(frame(GZIP, &compressed));
+    }
+  }
+
+  Ok(frame(IDENTITY, data))
+}
+
+fn frame(encoding: u8, data: &[u8]) -> Vec<u8> {
+  let mut framed = Vec::with_capacity(FRAME_MAGIC.len() + 1 + data.len());
+  framed.extend_from_slice(FRAME_MAGIC);
+  framed.push(encoding);
+  framed.extend_from_slice(data);
+  framed
+}
+
+/// Unwraps a stored paste as it's read, only the header is read right away. Compressed
+/// pastes are passed through as they are when the client accepts gzip and decompressed on
+/// the way out otherwise.
+pub fn decode(mut stored: PasteReader, accepts_gzip: bool) -> io::Result<Decoded> {
+  let mut header = Vec::with_capacity(FRAME_MAGIC.len() + 1);
+  (&mut stored)
+    .take(FRAME_MAGIC.len() as u64 + 1)
+    .read_to_end(&mut header)?;
+
+  if !header.starts_with(FRAME_MAGIC) || header.len() <= FRAME_MAGIC.len() {
+    return Ok(Decoded {
+      body: Box::new(Cursor::new(header).chain(stored)),
+      content_encoding: None,
+    });
+  }
+
+  let encoding = header[FRAME_MAGIC.len()];
+  match encoding {
+    IDENTITY => Ok(Decoded {
+      body: stored,
+      content_encoding: None,
+    }),
+    GZIP if accepts_gzip => Ok(Decoded {
+      body: stored,
+      content_encoding: Some("gzip"),
+    }),
+    GZIP => Ok(Decoded {
+      body: Box::new(GzDecoder::new(stored)),
+      content_encoding: None,
+    }),
+    _ => Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      format!("unknown paste encoding ({})", encoding),
+    )),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const LOG: &[u8] = b"ERROR something failed\nERROR something failed\nERROR something failed\n";
+
+  /// decodes the stored paste and reads all of it
+  fn decode_all(stored: Vec<u8>, accepts_gzip: bool) -> (Vec<u8>, Option<&'static str>) {
+    let mut decoded = decode(Box::new(Cursor::new(stored)), accepts_gzip).unwrap();
+    let mut body = vec![];
+    decoded.body.read_to_end(&mut body).unwrap();
+    (body, decoded.content_encoding)
+  }
+
+  #[test]
+  fn test_compressed_round_trip() {
+    let stored = encode(LOG, true).unwrap();
+    assert!(stored.len() < LOG.len());
+
+    let (body, content_encoding) = decode_all(stored.clone(), false);
+    assert_eq!(LOG, &body[..]);
+    assert_eq!(None, content_encoding);
+
+    let (body, content_encoding) = decode_all(stored, true);
+    assert_eq!(Some("gzip"), content_encoding);
+    let mut decompressed = vec![];
+    GzDecoder::new(&body[..])
+      .read_to_end(&mut decompressed)
+      .unwrap();
+    assert_eq!(LOG, &decompressed[..]);
+  }
+
+  #[test]
+  fn test_incompressible_pastes_are_stored_as_is() {
+    let (body, content_encoding) = decode_all(encode(b"abc", true).unwrap(), true);
+    assert_eq!(b"abc", &body[..]);
+    assert_eq!(None, content_encoding);
+  }
+
+  #[test]
+  fn test_unframed_pastes_are_served_raw() {
+    let (body, content_encoding) = decode_all(b"written before compression".to_vec(), true);
+    assert_eq!(b"written before compression", &body[..]);
+    assert_eq!(None, content_encoding);
+  }
+}
@@ -1,136 +1,209 @@
-use crate::util::get_deletion_file_name_with_path;
-use chrono::{self, Duration, Utc};
+use crate::handle_err;
+use crate::index::MetadataIndex;
+use crate::store::{view_marker, PasteStore};
+use chrono::{self, DateTime, Duration, SubsecRound, Utc};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
-use std::fs::{self, File, OpenOptions};
-use std::io::prelude::*;
 use std::ops::{Add, Sub};
-use std::path::Path;
 use std::result::Result;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Record {
   pub expiry: u64,
   pub key: String,
-  pub created_time: String,
+  /// whole seconds, in UTC like everything else we date, see `created_time_format`
+  #[serde(with = "created_time_format")]
+  pub created_time: DateTime<Utc>,
+  /// size of the stored paste in bytes
+  #[serde(default)]
+  pub size: u64,
+  #[serde(default)]
+  pub content_type: Option<String>,
+  /// the paste is an opaque envelope encrypted in the browser, see `envelope::ClientEnvelope`
+  #[serde(default)]
+  pub client_encrypted: bool,
+  /// one-shot paste, deleted as soon as it's read for the first time
+  #[serde(default)]
+  pub burn_after_reading: bool,
+  /// number of reads left before the paste expires, `None` when only its expiry counts
+  #[serde(default)]
+  pub remaining_views: Option<u64>,
+  /// sliding expiry, every read pushes the expiry to this many seconds after the read
+  #[serde(default)]
+  pub idle_window: Option<u64>,
+  /// unix timestamp of the last read, only kept for sliding expiry
+  #[serde(default)]
+  pub last_access: Option<i64>,
+  /// in seconds after creation, how far sliding expiry can push `expiry` at most
+  #[serde(default)]
+  pub expiry_ceiling: Option<u64>,
+  /// never expires, `expiry` doesn't mean anything then
+  #[serde(default)]
+  pub permanent: bool,
 }
 
 /// DEFAULT_EXPIRY = 604_800 => seconds for 1 week
 pub const DEFAULT_EXPIRY: u64 = 604_800;
 
+/// DEFAULT_MAX_RETENTION = 2_592_000 => seconds for 30 days, the longest `TimeParam` allows
+pub const DEFAULT_MAX_RETENTION: u64 = 2_592_000;
+
+/// PERMANENT_TTL = 3_153_600_000 => seconds for 100 years, how long permanent pastes count as
+/// remaining wherever a finite time is needed, like the cache
+pub const PERMANENT_TTL: i64 = 3_153_600_000;
+
 impl Record {
   /// key => Unique ID | expiry => in seconds
   pub fn new(key: String, expiry: u64) -> Self {
-    let created_time = chrono::offset::Local::now().to_rfc2822();
+    let created_time = Utc::now().trunc_subsecs(0);
     Record {
       expiry,
       key,
       created_time,
+      size: 0,
+      content_type: None,
+      client_encrypted: false,
+      burn_after_reading: false,
+      remaining_views: None,
+      idle_window: None,
+      last_access: None,
+      expiry_ceiling: None,
+      permanent: false,
     }
   }
 
-  fn internal_log(&self, filename: &str) -> Result<(), Box<dyn Error>> {
-    if !Path::new(&filename).exists() {
-      let mut f = File::create(&filename)?;
-      f.flush()?;
+  /// key => Unique ID, for a paste that never expires
+  pub fn permanent(key: String) -> Self {
+    Record {
+      permanent: true,
+      ..Record::new(key, 0)
     }
-
-    let mut file = OpenOptions::new().write(true).append(true).open(filename)?;
-    let json_data = serde_json::to_string(&self)?;
-    writeln!(file, "{}", json_data)?;
-    file.flush()?;
-
-    Ok(())
   }
 
-  pub fn log(&self) -> Result<(), Box<dyn Error>> {
-    let (file_path_with_name, _) = get_deletion_file_name_with_path(0);
-    self.internal_log(&file_path_with_name)?;
-    Ok(())
+  /// unix timestamp of when the record was created
+  pub fn created_timestamp(&self) -> i64 {
+    self.created_time.timestamp()
   }
 
-  /// date => 2006-01-25
-  pub fn log_to_particular_day(&self, date: &str) -> Result<(), Box<dyn Error>> {
-    let filename = format!("deletions/{}.txt", date);
-    self.internal_log(&filename)?;
+  pub fn log(&self, index: &MetadataIndex) -> Result<(), Box<dyn Error>> {
+    index.insert(self)?;
     Ok(())
   }
 
-  /// date => 2006-01-25
-  pub fn delete_record_and_file(key: &str, date: &str) -> Result<(), Box<dyn Error>> {
-    Record::delete_file(&key)?;
-
-    let filename = format!("deletions/{}.txt", date);
+  pub fn delete_record_and_file(
+    store: &dyn PasteStore,
+    index: &MetadataIndex,
+    key: &str,
+  ) -> Result<(), Box<dyn Error>> {
+    Record::delete_file(store, key)?;
+    Record::delete_record(index, key)?;
 
-    let mut file = File::open(&filename)?;
+    Ok(())
+  }
 
-    let mut file_contents = String::new();
+  /// returns `true` when there was a record to delete
+  pub fn delete_record(index: &MetadataIndex, key: &str) -> Result<bool, Box<dyn Error>> {
+    Ok(index.remove(key)?)
+  }
 
-    file.read_to_string(&mut file_contents)?;
+  /// Pushes the expiry back by the given number of seconds. The ceiling of a sliding expiry
+  /// moves along with it.
+  pub fn extend(&mut self, index: &MetadataIndex, seconds: u64) -> Result<(), Box<dyn Error>> {
+    if self.permanent {
+      return Err(format!("({}) never expires", self.key).into());
+    }
 
-    file.flush()?;
+    self.expiry += seconds;
+    if let Some(ceiling) = self.expiry_ceiling.as_mut() {
+      *ceiling += seconds;
+    }
 
-    let filtered_records = file_contents
-      .split("\n")
-      .filter(|line| line != &"")
-      .map(|line| {
-        serde_json::from_str::<Record>(line).expect("expected a JSON but found something else.")
-      })
-      .filter(|record| record.key != key)
-      .map(|record| {
-        serde_json::to_string::<Record>(&record).expect("expected record to be converted to JSON")
-      })
-      .reduce(|a, b| format!("{}\n{}", a, b));
+    self.reschedule(index)
+  }
 
-    let filtered_records = match filtered_records {
-      Some(r) => r,
-      None => String::new(),
+  /// Slides the expiry of a paste with an idle window to a full window after `now` (unix
+  /// timestamp), without going past its ceiling. Returns `false` for pastes without one.
+  pub fn touch(&mut self, index: &MetadataIndex, now: i64) -> Result<bool, Box<dyn Error>> {
+    let idle_window = match self.idle_window {
+      Some(idle_window) => idle_window,
+      None => return Ok(false),
     };
 
-    let mut file = OpenOptions::new()
-      .write(true)
-      .truncate(true)
-      .open(filename)?;
-
-    file.write_all(&filtered_records.as_bytes())?;
-
-    file.flush()?;
+    let slid = (now - self.created_timestamp()).max(0) as u64 + idle_window;
+    self.expiry = match self.expiry_ceiling {
+      Some(ceiling) => slid.min(ceiling),
+      None => slid,
+    }
+    .max(self.expiry);
+    self.last_access = Some(now);
 
-    Ok(())
+    self.reschedule(index)?;
+    Ok(true)
   }
 
-  pub fn delete_file(record_id: &str) -> Result<bool, Box<dyn Error>> {
-    let data_file = format!("upload/{}", record_id);
-    let data_file_path = Path::new(&data_file);
-    if data_file_path.exists() {
-      fs::remove_file(data_file_path)?;
-      return Ok(true);
+  /// Counts a read of a paste with a view limit. Returns the number of views left after this
+  /// one, or `None` when there were none left to take.
+  ///
+  /// Every instance sharing a store only counts the reads it served itself, so there each view
+  /// is taken by creating its marker instead, see `store::view_marker`, which only one reader
+  /// gets to. The views left we know of are never fewer than the ones really left, the markers
+  /// above them are all taken already, so that's where the search for a free one starts.
+  pub fn take_view(
+    &mut self,
+    store: &dyn PasteStore,
+    index: &MetadataIndex,
+  ) -> Result<Option<u64>, Box<dyn Error>> {
+    if !store.is_shared() {
+      let views_left = index.take_view(&self.key)?;
+      if views_left.is_some() {
+        self.remaining_views = views_left;
+      }
+      return Ok(views_left);
     }
 
-    Ok(false)
-  }
-
-  fn internal_get_deletions(seconds: i64) -> String {
-    let days_to_add = (seconds as f32 / 86_400 as f32).ceil() as i64;
+    let mut views = self.remaining_views.unwrap_or(0);
+    while views > 0 && !store.create_marker(&view_marker(&self.key, views))? {
+      views -= 1;
+    }
 
-    let date = Utc::now()
-      .checked_add_signed(Duration::days(days_to_add))
-      .unwrap();
+    let views_left = views.saturating_sub(1);
+    index.lower_views(&self.key, views_left)?;
+    if views == 0 {
+      return Ok(None);
+    }
 
-    date.format(crate::util::SIMPLE_DATE_FORMAT).to_string()
+    self.remaining_views = Some(views_left);
+    // instances adopting the record later start from the views that are left, the view is
+    // ours either way
+    if views_left > 0 {
+      handle_err!(
+        store.put_record(self),
+        "Error while writing the views left of a paste",
+        {}
+      );
+    }
+    Ok(Some(views_left))
   }
 
-  pub fn get_deletions_date_for_default_expiry() -> String {
-    Record::internal_get_deletions(DEFAULT_EXPIRY as i64)
+  /// writes the current expiry to the index
+  fn reschedule(&self, index: &MetadataIndex) -> Result<(), Box<dyn Error>> {
+    if !index.reschedule(self)? {
+      return Err(format!("no record found for ({})", self.key).into());
+    }
+
+    Ok(())
   }
 
-  pub fn get_deletions_date_for_number_of_days(seconds_to_add: i64) -> String {
-    Record::internal_get_deletions(seconds_to_add)
+  pub fn delete_file(store: &dyn PasteStore, record_id: &str) -> Result<bool, Box<dyn Error>> {
+    Ok(store.delete(record_id)?)
   }
 
   pub fn is_key_expired(&self) -> bool {
-    let timestamp = chrono::DateTime::parse_from_rfc2822(&self.created_time).unwrap();
-    let added = timestamp.add(Duration::seconds(self.expiry as i64));
+    if self.permanent {
+      return false;
+    }
+
+    let added = self.created_time.add(Duration::seconds(self.expiry as i64));
 
     let now = Utc::now();
 
@@ -141,9 +214,13 @@ impl Record {
     false
   }
 
+  /// in seconds, `PERMANENT_TTL` for permanent pastes
   pub fn remaining_time_to_expiry(&self) -> i64 {
-    let created_timestamp = chrono::DateTime::parse_from_rfc2822(&self.created_time).unwrap();
-    let created_timestamp = created_timestamp.timestamp() + self.expiry as i64;
+    if self.permanent {
+      return PERMANENT_TTL;
+    }
+
+    let created_timestamp = self.created_timestamp() + self.expiry as i64;
 
     let current_timestamp = Utc::now().timestamp();
 
@@ -155,35 +232,31 @@ impl Record {
 
     difference
   }
+}
 
-  /// date => 2006-01-25
-  pub fn delete_all_records_from_the_deletions_and_itself(
-    date: &str,
-  ) -> Result<(), Box<dyn Error>> {
-    let filename = format!("deletions/{}.txt", date);
-
-    let mut file = File::open(&filename)?;
-
-    let mut contents = String::new();
-
-    file.read_to_string(&mut contents)?;
-
-    for line in contents.split("\n") {
-      if line.is_empty() {
-        continue;
-      }
-      let record = serde_json::from_str::<Record>(line)?;
-
-      let upload_filename = format!("upload/{}", record.key);
-
-      if Path::new(&upload_filename).exists() {
-        fs::remove_file(upload_filename)?;
-      }
-    }
+/// `created_time` is written as RFC 3339 in UTC. Records written before that carry an RFC 2822
+/// string in the local time of whichever host wrote them, or just a date, both still read fine.
+mod created_time_format {
+  use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+  use serde::{de, Deserialize, Deserializer, Serializer};
 
-    fs::remove_file(filename)?;
+  pub fn serialize<S: Serializer>(time: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&time.to_rfc3339())
+  }
 
-    Ok(())
+  pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<DateTime<Utc>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+
+    DateTime::parse_from_rfc3339(&value)
+      .or_else(|_| DateTime::parse_from_rfc2822(&value))
+      .map(|time| time.with_timezone(&Utc))
+      .or_else(|_| {
+        NaiveDate::parse_from_str(&value, crate::util::SIMPLE_DATE_FORMAT)
+          .map(|date| Utc.from_utc_datetime(&date.and_hms(0, 0, 0)))
+      })
+      .map_err(|_| de::Error::custom(format!("invalid created_time ({})", value)))
   }
 }
 
@@ -200,38 +273,8 @@ mod tests {
   use std::thread;
 
   use super::*;
-
-  #[test]
-  fn test_for_deletions_date() {
-    assert_eq!(
-      "2021-07-13",
-      Record::get_deletions_date_for_number_of_days(86400)
-    );
-  }
-
-  #[test]
-  fn test_for_deletions_date_2() {
-    assert_eq!(
-      "2021-07-19",
-      Record::get_deletions_date_for_number_of_days(604_800)
-    );
-  }
-
-  #[test]
-  fn test_for_deletions_date_3() {
-    assert_eq!(
-      "2021-07-15",
-      Record::get_deletions_date_for_number_of_days(259_200)
-    );
-  }
-
-  #[test]
-  fn test_for_deletions_date_4() {
-    assert_eq!(
-      "2021-07-09",
-      Record::get_deletions_date_for_number_of_days(-259_200)
-    );
-  }
+  use crate::s3::tests::fake_store;
+  use chrono::TimeZone;
 
   #[test]
   fn test_for_from_method() {
@@ -239,12 +282,24 @@ mod tests {
     assert_eq!(15, Record::from(data).expiry);
   }
 
+  #[test]
+  fn test_created_time_of_older_records() {
+    let data =
+      r#"{"expiry": 15, "key": "u7F1", "created_time": "Sun, 11 Jul 2021 01:30:00 +0530"}"#;
+    let record = Record::from(data.to_string());
+    assert_eq!(Utc.ymd(2021, 7, 10).and_hms(20, 0, 0), record.created_time);
+
+    let written = serde_json::to_string(&record).unwrap();
+    assert!(written.contains(r#""created_time":"2021-07-10T20:00:00+00:00""#));
+    assert_eq!(record.created_time, Record::from(written).created_time);
+  }
+
   #[test]
   #[should_panic]
   #[ignore]
   fn test_for_from_method_2() {
     let data = String::from(r#"{"expiry": 15, "key": 89, "created_time": "2021-07-11"}"#);
-    Record::from(data);
+    let _ = Record::from(data);
   }
 
   #[test]
@@ -279,4 +334,94 @@ mod tests {
     let r = Record::new("abcd".to_string(), 30);
     assert_eq!(30, r.remaining_time_to_expiry());
   }
+
+  #[test]
+  fn test_extend() {
+    let index = MetadataIndex::open_in_memory().unwrap();
+    let mut r = Record::new("abcd".to_string(), 60);
+    r.log(&index).unwrap();
+
+    r.extend(&index, 3 * 86_400).unwrap();
+    assert_eq!(60 + 3 * 86_400, index.get("abcd").unwrap().unwrap().expiry);
+    assert_eq!(
+      vec![("abcd".to_string(), r.created_timestamp() + 60 + 3 * 86_400)],
+      index.expiry_schedule().unwrap()
+    );
+
+    assert!(Record::new("efgh".to_string(), 60)
+      .extend(&index, 60)
+      .is_err());
+  }
+
+  #[test]
+  fn test_touch_slides_up_to_the_ceiling() {
+    let index = MetadataIndex::open_in_memory().unwrap();
+    let mut r = Record::new("abcd".to_string(), 3_600);
+    r.idle_window = Some(3_600);
+    r.expiry_ceiling = Some(2 * 86_400);
+    r.log(&index).unwrap();
+    let created = r.created_timestamp();
+
+    assert!(r.touch(&index, created + 86_400).unwrap());
+    let stored = index.get("abcd").unwrap().unwrap();
+    assert_eq!(86_400 + 3_600, stored.expiry);
+    assert_eq!(Some(created + 86_400), stored.last_access);
+
+    r.touch(&index, created + 2 * 86_400).unwrap();
+    assert_eq!(2 * 86_400, index.get("abcd").unwrap().unwrap().expiry);
+
+    assert!(!Record::new("efgh".to_string(), 60)
+      .touch(&index, created)
+      .unwrap());
+  }
+
+  #[test]
+  fn test_permanent() {
+    let index = MetadataIndex::open_in_memory().unwrap();
+    let mut r = Record::permanent("abcd".to_string());
+    r.log(&index).unwrap();
+
+    assert!(!r.is_key_expired());
+    assert_eq!(PERMANENT_TTL, r.remaining_time_to_expiry());
+    assert!(r.extend(&index, 60).is_err());
+
+    assert!(index.get("abcd").unwrap().unwrap().permanent);
+    assert!(index.expiry_schedule().unwrap().is_empty());
+    assert!(index.soonest_expiring(10).unwrap().is_empty());
+    assert_eq!(1, index.live_records(i64::MAX).unwrap().len());
+    assert!(!index.remove_if_expired("abcd", i64::MAX).unwrap());
+  }
+
+  #[test]
+  fn test_views_are_counted_across_instances() {
+    let store = fake_store();
+    let ours = MetadataIndex::open_in_memory().unwrap();
+    let theirs = MetadataIndex::open_in_memory().unwrap();
+
+    let mut r = Record::new("abcd".to_string(), 60);
+    r.remaining_views = Some(3);
+    store.put("abcd", b"hello").unwrap();
+    store.put_record(&r).unwrap();
+    r.log(&ours).unwrap();
+    r.log(&theirs).unwrap();
+
+    let mut our_copy = ours.get("abcd").unwrap().unwrap();
+    let mut their_copy = theirs.get("abcd").unwrap().unwrap();
+    assert_eq!(Some(2), our_copy.take_view(&store, &ours).unwrap());
+    assert_eq!(Some(1), their_copy.take_view(&store, &theirs).unwrap());
+    assert_eq!(
+      Some(1),
+      store.get_record("abcd").unwrap().unwrap().remaining_views
+    );
+    assert_eq!(Some(0), our_copy.take_view(&store, &ours).unwrap());
+    assert_eq!(None, their_copy.take_view(&store, &theirs).unwrap());
+    assert_eq!(
+      Some(0),
+      theirs.get("abcd").unwrap().unwrap().remaining_views
+    );
+
+    // the markers go with the paste
+    store.delete("abcd").unwrap();
+    assert!(store.create_marker(&view_marker("abcd", 1)).unwrap());
+  }
 }
@@ -0,0 +1,314 @@
+use crate::core::Record;
+use crate::store::{PasteStore, SharedPasteStore};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::sync::Arc;
+
+/// Encrypted pastes start with this header, followed by the length of the key ID, the key
+/// ID itself and the 24 byte nonce. Everything after that is the ciphertext.
+const ENCRYPTION_MAGIC: &[u8] = b"PBE1";
+const NONCE_LENGTH: usize = 24;
+
+/// The XChaCha20-Poly1305 keys pastes are encrypted with, by key ID.
+///
+/// New pastes are always encrypted with the active key, the other keys are only kept
+/// around to read pastes written before a rotation.
+pub struct Keyring {
+  keys: HashMap<String, Key>,
+  active: String,
+}
+
+fn invalid_data(message: String) -> io::Error {
+  io::Error::new(ErrorKind::InvalidData, message)
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+  if !value.len().is_multiple_of(2) {
+    return None;
+  }
+
+  (0..value.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+    .collect()
+}
+
+impl Keyring {
+  /// keys => `2021-07:<64 hex chars>,2021-08:<64 hex chars>` | active => key ID new pastes are
+  /// encrypted with, the last listed key when `None`
+  pub fn parse(keys: &str, active: Option<&str>) -> Result<Self, String> {
+    let mut parsed = HashMap::new();
+    let mut last = None;
+
+    for entry in keys
+      .split(',')
+      .map(|entry| entry.trim())
+      .filter(|e| !e.is_empty())
+    {
+      let (id, hex) = entry
+        .split_once(':')
+        .ok_or_else(|| format!("expected `<key id>:<hex key>` but found ({})", entry))?;
+
+      if id.is_empty() || id.len() > u8::MAX as usize {
+        return Err(format!("invalid key ID ({})", id));
+      }
+
+      let key = decode_hex(hex)
+        .filter(|key| key.len() == 32)
+        .ok_or_else(|| format!("key ({}) has to be 32 bytes written as 64 hex chars", id))?;
+
+      parsed.insert(id.to_string(), *Key::from_slice(&key));
+      last = Some(id.to_string());
+    }
+
+    let active = match active {
+      Some(active) => active.to_string(),
+      None => last.ok_or_else(|| "no encryption keys were given".to_string())?,
+    };
+
+    if !parsed.contains_key(&active) {
+      return Err(format!(
+        "active key ({}) is not among the given keys",
+        active
+      ));
+    }
+
+    Ok(Keyring {
+      keys: parsed,
+      active,
+    })
+  }
+
+  pub fn active_key_id(&self) -> &str {
+    &self.active
+  }
+
+  /// Key the `dedup::DedupStore` blob names are computed with, derived from the active key so
+  /// the names of encrypted blobs don't give away a digest of their plaintext. Blobs written
+  /// before a rotation keep the names they got, they're looked up through the index anyway.
+  pub fn blob_naming_key(&self) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.keys[&self.active])
+      .expect("HMAC takes keys of any length");
+    mac.update(b"pastebin dedup blob names");
+    mac.finalize().into_bytes().to_vec()
+  }
+
+  pub fn encrypt(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+    let cipher = XChaCha20Poly1305::new(&self.keys[&self.active]);
+    let mut nonce = [0u8; NONCE_LENGTH];
+    rand::thread_rng().fill(&mut nonce[..]);
+
+    // the key ID goes into the associated data so the header can't be swapped unnoticed
+    let ciphertext = cipher
+      .encrypt(
+        XNonce::from_slice(&nonce),
+        Payload {
+          msg: data,
+          aad: self.active.as_bytes(),
+        },
+      )
+      .map_err(|_| io::Error::other("error trying to encrypt the paste"))?;
+
+    let mut stored = Vec::with_capacity(
+      ENCRYPTION_MAGIC.len() + 1 + self.active.len() + NONCE_LENGTH + ciphertext.len(),
+    );
+    stored.extend_from_slice(ENCRYPTION_MAGIC);
+    stored.push(self.active.len() as u8);
+    stored.extend_from_slice(self.active.as_bytes());
+    stored.extend_from_slice(&nonce);
+    stored.extend_from_slice(&ciphertext);
+
+    Ok(stored)
+  }
+
+  /// ID of the key the stored paste was encrypted with, `None` for plaintext pastes
+  pub fn key_id_of(stored: &[u8]) -> Option<&str> {
+    let rest = stored.strip_prefix(ENCRYPTION_MAGIC)?;
+    let length = *rest.first()? as usize;
+    std::str::from_utf8(rest.get(1..1 + length)?).ok()
+  }
+
+  /// Plaintext pastes, written before encryption was turned on, are returned as they are.
+  pub fn decrypt(&self, stored: Vec<u8>) -> io::Result<Vec<u8>> {
+    let key_id = match Keyring::key_id_of(&stored) {
+      Some(key_id) => key_id,
+      None => return Ok(stored),
+    };
+
+    let key = self
+      .keys
+      .get(key_id)
+      .ok_or_else(|| invalid_data(format!("paste was encrypted with unknown key ({})", key_id)))?;
+
+    let nonce_start = ENCRYPTION_MAGIC.len() + 1 + key_id.len();
+    let nonce = stored
+      .get(nonce_start..nonce_start + NONCE_LENGTH)
+      .ok_or_else(|| invalid_data("encrypted paste is truncated".to_string()))?;
+
+    XChaCha20Poly1305::new(key)
+      .decrypt(
+        XNonce::from_slice(nonce),
+        Payload {
+          msg: &stored[nonce_start + NONCE_LENGTH..],
+          aad: key_id.as_bytes(),
+        },
+      )
+      .map_err(|_| {
+        invalid_data(format!(
+          "error trying to decrypt the paste with key ({})",
+          key_id
+        ))
+      })
+  }
+}
+
+/// Encrypts everything written to the inner store and decrypts it on the way back.
+pub struct EncryptedStore {
+  inner: SharedPasteStore,
+  keyring: Arc<Keyring>,
+}
+
+impl EncryptedStore {
+  pub fn new(inner: SharedPasteStore, keyring: Arc<Keyring>) -> Self {
+    EncryptedStore { inner, keyring }
+  }
+}
+
+impl PasteStore for EncryptedStore {
+  fn put(&self, id: &str, data: &[u8]) -> io::Result<()> {
+    self.inner.put(id, &self.keyring.encrypt(data)?)
+  }
+
+  fn get(&self, id: &str) -> io::Result<Option<Vec<u8>>> {
+    match self.inner.get(id)? {
+      Some(stored) => Ok(Some(self.keyring.decrypt(stored)?)),
+      None => Ok(None),
+    }
+  }
+
+  fn delete(&self, id: &str) -> io::Result<bool> {
+    self.inner.delete(id)
+  }
+
+  fn exists(&self, id: &str) -> bool {
+    self.inner.exists(id)
+  }
+
+  fn list(&self) -> io::Result<Vec<String>> {
+    self.inner.list()
+  }
+
+  fn is_shared(&self) -> bool {
+    self.inner.is_shared()
+  }
+
+  fn put_record(&self, record: &Record) -> io::Result<()> {
+    self.inner.put_record(record)
+  }
+
+  fn get_record(&self, id: &str) -> io::Result<Option<Record>> {
+    self.inner.get_record(id)
+  }
+
+  fn list_records(&self) -> io::Result<Vec<String>> {
+    self.inner.list_records()
+  }
+
+  fn create_marker(&self, name: &str) -> io::Result<bool> {
+    self.inner.create_marker(name)
+  }
+
+  fn delete_marker(&self, name: &str) -> io::Result<()> {
+    self.inner.delete_marker(name)
+  }
+
+  fn modified(&self, id: &str) -> io::Result<Option<DateTime<Utc>>> {
+    self.inner.modified(id)
+  }
+}
+
+/// Rewrites every entry of the (raw, undecorated) store that isn't encrypted with the
+/// active key yet, plaintext ones included. Returns the number of entries rewritten.
+pub fn reencrypt(store: &dyn PasteStore, keyring: &Keyring) -> io::Result<u32> {
+  let mut counter: u32 = 0;
+  for id in store.list()? {
+    let stored = match store.get(&id)? {
+      Some(stored) => stored,
+      // deleted in the meantime
+      None => continue,
+    };
+
+    if Keyring::key_id_of(&stored) == Some(keyring.active_key_id()) {
+      continue;
+    }
+
+    let plaintext = keyring.decrypt(stored)?;
+    store.put(&id, &keyring.encrypt(&plaintext)?)?;
+    counter += 1;
+  }
+
+  Ok(counter)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::store::MemoryStore;
+
+  const OLD_KEY: &str = "old:000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+  const NEW_KEY: &str = "new:1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+  #[test]
+  fn test_keyring_parse() {
+    assert!(Keyring::parse("", None).is_err());
+    assert!(Keyring::parse("k1:abcd", None).is_err());
+    assert!(Keyring::parse(OLD_KEY, Some("new")).is_err());
+
+    let keyring = Keyring::parse(&format!("{},{}", OLD_KEY, NEW_KEY), None).unwrap();
+    assert_eq!("new", keyring.active_key_id());
+  }
+
+  #[test]
+  fn test_encrypted_store_round_trip() {
+    let inner: SharedPasteStore = Arc::new(MemoryStore::new());
+    let keyring = Arc::new(Keyring::parse(OLD_KEY, None).unwrap());
+    let store = EncryptedStore::new(inner.clone(), keyring);
+
+    store.put("abcd", b"internal config").unwrap();
+    let stored = inner.get("abcd").unwrap().unwrap();
+    assert_eq!(Some("old"), Keyring::key_id_of(&stored));
+    assert!(!stored.windows(8).any(|w| w == b"internal"));
+    assert_eq!(
+      Some(b"internal config".to_vec()),
+      store.get("abcd").unwrap()
+    );
+  }
+
+  #[test]
+  fn test_reencrypt_moves_pastes_to_the_active_key() {
+    let store = MemoryStore::new();
+    let old = Keyring::parse(OLD_KEY, None).unwrap();
+    store.put("abcd", &old.encrypt(b"secret").unwrap()).unwrap();
+    store.put("efgh", b"plaintext").unwrap();
+
+    let rotated = Keyring::parse(&format!("{},{}", OLD_KEY, NEW_KEY), None).unwrap();
+    assert_eq!(2, reencrypt(&store, &rotated).unwrap());
+    assert_eq!(0, reencrypt(&store, &rotated).unwrap());
+
+    let stored = store.get("abcd").unwrap().unwrap();
+    assert_eq!(Some("new"), Keyring::key_id_of(&stored));
+    assert_eq!(b"secret".to_vec(), rotated.decrypt(stored).unwrap());
+    assert_eq!(
+      b"plaintext".to_vec(),
+      rotated
+        .decrypt(store.get("efgh").unwrap().unwrap())
+        .unwrap()
+    );
+  }
+}
@@ -0,0 +1,289 @@
+use crate::core::Record;
+use crate::index::SharedMetadataIndex;
+use crate::store::{PasteReader, PasteStore, SharedPasteStore};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io;
+use std::sync::Mutex;
+
+/// Content-addressed layer on top of another `PasteStore`.
+///
+/// Paste bodies are stored once under their SHA-256 digest and the paste IDs only point
+/// at them (the links and reference counts live in the metadata index). A blob is deleted
+/// from the inner store when the last paste referencing it is deleted.
+///
+/// With a naming key, blobs are named by an HMAC-SHA256 of their content instead. Over an
+/// `EncryptedStore` that keeps anyone who can list the storage from confirming a guessed
+/// paste by its digest.
+///
+/// Pastes written before the layer was enabled are still stored under their ID and keep
+/// working.
+pub struct DedupStore {
+  blobs: SharedPasteStore,
+  index: SharedMetadataIndex,
+  naming_key: Option<Vec<u8>>,
+  // serializes the blob writes and deletes so a blob can't be deleted under a paste that
+  // is just being linked to it
+  lock: Mutex<()>,
+}
+
+fn index_err(e: rusqlite::Error) -> io::Error {
+  io::Error::other(e)
+}
+
+pub fn digest_of(data: &[u8]) -> String {
+  format!("{:x}", Sha256::digest(data))
+}
+
+fn is_digest(name: &str) -> bool {
+  name.len() == 64 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+impl DedupStore {
+  /// naming_key => see `crypto::Keyring::blob_naming_key`, `None` names blobs by their plain
+  /// SHA-256 digest
+  pub fn new(
+    blobs: SharedPasteStore,
+    index: SharedMetadataIndex,
+    naming_key: Option<Vec<u8>>,
+  ) -> Self {
+    DedupStore {
+      blobs,
+      index,
+      naming_key,
+      lock: Mutex::new(()),
+    }
+  }
+
+  fn blob_name(&self, data: &[u8]) -> String {
+    match &self.naming_key {
+      Some(key) => {
+        let mut mac =
+          <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes keys of any length");
+        mac.update(data);
+        format!("{:x}", mac.finalize().into_bytes())
+      }
+      None => digest_of(data),
+    }
+  }
+}
+
+impl PasteStore for DedupStore {
+  fn put(&self, id: &str, data: &[u8]) -> io::Result<()> {
+    let digest = self.blob_name(data);
+    let _guard = self
+      .lock
+      .lock()
+      .map_err(|_| io::Error::other("dedup lock was poisoned"))?;
+
+    if !self.blobs.exists(&digest) {
+      self.blobs.put(&digest, data)?;
+    }
+    self
+      .index
+      .link_blob(id, &digest, data.len() as u64)
+      .map_err(index_err)?;
+
+    Ok(())
+  }
+
+  fn get(&self, id: &str) -> io::Result<Option<Vec<u8>>> {
+    match self.index.blob_for(id).map_err(index_err)? {
+      Some(digest) => self.blobs.get(&digest),
+      None => self.blobs.get(id),
+    }
+  }
+
+  fn open(&self, id: &str) -> io::Result<Option<PasteReader>> {
+    match self.index.blob_for(id).map_err(index_err)? {
+      Some(digest) => self.blobs.open(&digest),
+      None => self.blobs.open(id),
+    }
+  }
+
+  fn modified(&self, id: &str) -> io::Result<Option<DateTime<Utc>>> {
+    match self.index.blob_for(id).map_err(index_err)? {
+      Some(digest) => self.blobs.modified(&digest),
+      None => self.blobs.modified(id),
+    }
+  }
+
+  fn delete(&self, id: &str) -> io::Result<bool> {
+    let _guard = self
+      .lock
+      .lock()
+      .map_err(|_| io::Error::other("dedup lock was poisoned"))?;
+
+    match self.index.unlink_blob(id).map_err(index_err)? {
+      Some((digest, refcount)) => {
+        if refcount <= 0 {
+          self.blobs.delete(&digest)?;
+        }
+        Ok(true)
+      }
+      None => self.blobs.delete(id),
+    }
+  }
+
+  fn exists(&self, id: &str) -> bool {
+    match self.index.blob_for(id) {
+      Ok(Some(digest)) => self.blobs.exists(&digest),
+      _ => self.blobs.exists(id),
+    }
+  }
+
+  fn list(&self) -> io::Result<Vec<String>> {
+    let mut ids: HashSet<String> = self
+      .index
+      .linked_keys()
+      .map_err(index_err)?
+      .into_iter()
+      .collect();
+    for name in self.blobs.list()? {
+      if !is_digest(&name) {
+        ids.insert(name);
+      }
+    }
+
+    Ok(ids.into_iter().collect())
+  }
+
+  fn is_shared(&self) -> bool {
+    self.blobs.is_shared()
+  }
+
+  fn put_record(&self, record: &Record) -> io::Result<()> {
+    self.blobs.put_record(record)
+  }
+
+  fn get_record(&self, id: &str) -> io::Result<Option<Record>> {
+    self.blobs.get_record(id)
+  }
+
+  fn list_records(&self) -> io::Result<Vec<String>> {
+    self.blobs.list_records()
+  }
+
+  fn create_marker(&self, name: &str) -> io::Result<bool> {
+    self.blobs.create_marker(name)
+  }
+
+  fn delete_marker(&self, name: &str) -> io::Result<()> {
+    self.blobs.delete_marker(name)
+  }
+
+  /// blobs without a reference count, `list` leaves them out. Holds the lock so none of them
+  /// gets linked to a new paste meanwhile
+  fn remove_unlinked(&self, older_than: DateTime<Utc>) -> io::Result<u32> {
+    let _guard = self
+      .lock
+      .lock()
+      .map_err(|_| io::Error::other("dedup lock was poisoned"))?;
+
+    let mut counter: u32 = 0;
+    for name in self.blobs.list()? {
+      if !is_digest(&name) || self.index.has_blob(&name).map_err(index_err)? {
+        continue;
+      }
+      if matches!(self.blobs.modified(&name)?, Some(modified) if modified > older_than) {
+        continue;
+      }
+
+      self.blobs.delete(&name)?;
+      counter += 1;
+    }
+
+    Ok(counter)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::crypto::{EncryptedStore, Keyring};
+  use crate::index::MetadataIndex;
+  use crate::store::MemoryStore;
+  use crate::util::remove_orphaned_pastes;
+  use std::sync::Arc;
+
+  #[test]
+  fn test_identical_pastes_share_a_blob() {
+    let blobs: SharedPasteStore = Arc::new(MemoryStore::new());
+    let index = Arc::new(MetadataIndex::open_in_memory().unwrap());
+    let store = DedupStore::new(blobs.clone(), index, None);
+
+    store.put("aaaa", b"same stack trace").unwrap();
+    store.put("bbbb", b"same stack trace").unwrap();
+    assert_eq!(1, blobs.list().unwrap().len());
+
+    assert!(store.delete("aaaa").unwrap());
+    assert_eq!(None, store.get("aaaa").unwrap());
+    assert_eq!(
+      Some(b"same stack trace".to_vec()),
+      store.get("bbbb").unwrap()
+    );
+
+    assert!(store.delete("bbbb").unwrap());
+    assert!(blobs.list().unwrap().is_empty());
+  }
+
+  #[test]
+  fn test_encrypted_blobs_are_not_named_by_their_plaintext_digest() {
+    let keyring = Arc::new(
+      Keyring::parse(
+        "2021-07:000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+        None,
+      )
+      .unwrap(),
+    );
+    let blobs: SharedPasteStore = Arc::new(MemoryStore::new());
+    let encrypted: SharedPasteStore = Arc::new(EncryptedStore::new(blobs.clone(), keyring.clone()));
+    let index = Arc::new(MetadataIndex::open_in_memory().unwrap());
+    let store = DedupStore::new(encrypted, index, Some(keyring.blob_naming_key()));
+
+    store.put("aaaa", b"hunter2").unwrap();
+    store.put("bbbb", b"hunter2").unwrap();
+
+    let names = blobs.list().unwrap();
+    assert_eq!(1, names.len());
+    assert!(!names.contains(&digest_of(b"hunter2")));
+    assert_eq!(Some(b"hunter2".to_vec()), store.get("bbbb").unwrap());
+  }
+
+  #[test]
+  fn test_unlinked_blobs_are_swept() {
+    let blobs: SharedPasteStore = Arc::new(MemoryStore::new());
+    let index = Arc::new(MetadataIndex::open_in_memory().unwrap());
+    let store = DedupStore::new(blobs.clone(), index.clone(), None);
+
+    store.put("aaaa", b"linked").unwrap();
+    blobs.put("OLD1", b"legacy").unwrap();
+    for key in ["aaaa", "OLD1"] {
+      index.insert(&Record::new(key.to_string(), 60)).unwrap();
+    }
+    // written for an upload that died before linking its paste
+    blobs.put(&digest_of(b"unlinked"), b"unlinked").unwrap();
+
+    assert_eq!(1, remove_orphaned_pastes(&store, &index).unwrap());
+    assert!(!blobs.exists(&digest_of(b"unlinked")));
+    assert_eq!(Some(b"linked".to_vec()), store.get("aaaa").unwrap());
+    assert!(blobs.exists("OLD1"));
+  }
+
+  #[test]
+  fn test_pastes_stored_before_dedup_still_work() {
+    let blobs: SharedPasteStore = Arc::new(MemoryStore::new());
+    blobs.put("OLD1", b"legacy").unwrap();
+    let index = Arc::new(MetadataIndex::open_in_memory().unwrap());
+    let store = DedupStore::new(blobs, index, None);
+
+    store.put("NEW1", b"fresh").unwrap();
+    let mut ids = store.list().unwrap();
+    ids.sort();
+    assert_eq!(vec!["NEW1".to_string(), "OLD1".to_string()], ids);
+    assert_eq!(Some(b"legacy".to_vec()), store.get("OLD1").unwrap());
+    assert!(store.delete("OLD1").unwrap());
+  }
+}
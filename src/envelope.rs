@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+/// Parameters the browser used to encrypt the paste, it needs them back to decrypt it.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CipherParams {
+  /// only `aes-gcm` for now, that's what WebCrypto gives us
+  pub cipher: String,
+  /// in bits => 128 | 192 | 256
+  pub key_size: u32,
+  /// in bits => 96 up to 128
+  pub tag_size: u32,
+  /// only `pbkdf2-sha256` for now
+  pub kdf: String,
+  pub iterations: u32,
+}
+
+/// Opaque ciphertext of a zero-knowledge paste, encrypted in the browser with a key the
+/// server never sees (it travels in the URL fragment). Only its structure is checked.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientEnvelope {
+  /// base64
+  pub iv: String,
+  /// base64
+  pub salt: String,
+  /// base64
+  pub ciphertext: String,
+  pub params: CipherParams,
+}
+
+const MIN_ITERATIONS: u32 = 10_000;
+const MAX_ITERATIONS: u32 = 10_000_000;
+
+fn decoded_len(field: &str, value: &str) -> Result<usize, String> {
+  base64::decode(value)
+    .map(|bytes| bytes.len())
+    .map_err(|_| format!("`{}` has to be base64 encoded", field))
+}
+
+impl ClientEnvelope {
+  /// Parses and validates the uploaded envelope, returns it re-serialized so nothing but
+  /// the known fields gets stored.
+  pub fn validate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let envelope = serde_json::from_slice::<ClientEnvelope>(data)
+      .map_err(|e| format!("expected an encrypted paste envelope. Error: {}", e))?;
+    let params = &envelope.params;
+
+    if params.cipher != "aes-gcm" {
+      return Err(format!("unsupported cipher ({})", params.cipher));
+    }
+
+    if params.kdf != "pbkdf2-sha256" {
+      return Err(format!("unsupported key derivation ({})", params.kdf));
+    }
+
+    if ![128, 192, 256].contains(&params.key_size) {
+      return Err(format!("unsupported key size ({})", params.key_size));
+    }
+
+    if params.tag_size < 96 || params.tag_size > 128 || params.tag_size % 8 != 0 {
+      return Err(format!("unsupported tag size ({})", params.tag_size));
+    }
+
+    if params.iterations < MIN_ITERATIONS || params.iterations > MAX_ITERATIONS {
+      return Err(format!(
+        "`iterations` has to be between {} and {}",
+        MIN_ITERATIONS, MAX_ITERATIONS
+      ));
+    }
+
+    let iv_len = decoded_len("iv", &envelope.iv)?;
+    if !(12..=16).contains(&iv_len) {
+      return Err(format!("`iv` has to be 12 to 16 bytes, found {}", iv_len));
+    }
+
+    if decoded_len("salt", &envelope.salt)? < 8 {
+      return Err("`salt` has to be at least 8 bytes".to_string());
+    }
+
+    if decoded_len("ciphertext", &envelope.ciphertext)? <= (params.tag_size / 8) as usize {
+      return Err("`ciphertext` is too short to hold anything".to_string());
+    }
+
+    serde_json::to_vec(&envelope).map_err(|e| e.to_string())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn envelope(iterations: u32, ciphertext: &str) -> String {
+    format!(
+      r#"{{"iv": "AAECAwQFBgcICQoL", "salt": "AAECAwQFBgc=", "ciphertext": "{}",
+          "params": {{"cipher": "aes-gcm", "key_size": 256, "tag_size": 128,
+                      "kdf": "pbkdf2-sha256", "iterations": {}}}}}"#,
+      ciphertext, iterations
+    )
+  }
+
+  #[test]
+  fn test_valid_envelope() {
+    let data = envelope(100_000, "AAECAwQFBgcICQoLDA0ODxAREhM=");
+    assert!(ClientEnvelope::validate(data.as_bytes()).is_ok());
+  }
+
+  #[test]
+  fn test_invalid_envelopes() {
+    assert!(ClientEnvelope::validate(b"plain text").is_err());
+    assert!(
+      ClientEnvelope::validate(envelope(1, "AAECAwQFBgcICQoLDA0ODxAREhM=").as_bytes()).is_err()
+    );
+    assert!(ClientEnvelope::validate(envelope(100_000, "AAEC").as_bytes()).is_err());
+    assert!(ClientEnvelope::validate(envelope(100_000, "not base64!").as_bytes()).is_err());
+  }
+}
@@ -0,0 +1,260 @@
+use crate::index::SharedMetadataIndex;
+use crate::store::SharedPasteStore;
+use chrono::Utc;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long the scheduler sleeps when nothing is scheduled at all.
+const IDLE_WAIT: Duration = Duration::from_secs(3_600);
+
+/// RETRY_IN = 60 => seconds, how long a paste of a shared store waits for another try when
+/// its record couldn't be read
+const RETRY_IN: i64 = 60;
+
+/// Deletes every paste, content and record, within a second of its expiry.
+///
+/// The pastes are kept in a min-heap by expiry, rebuilt from the metadata index on startup
+/// and fed by the index whenever a record gets inserted or rescheduled. Heap entries aren't
+/// updated in place, a rescheduled paste just gets a second entry and the superseded one is
+/// skipped. Once those make up half the heap, it's rebuilt without them, so pastes slid by
+/// every read don't pile up entries.
+///
+/// On a shared store another instance might have pushed the expiry back (`/extend`, a read
+/// sliding an idle expiry), so the store's copy of the record is checked before anything gets
+/// deleted.
+pub struct ExpiryScheduler {
+  heap: BinaryHeap<Reverse<(i64, String)>>,
+  /// the latest expiry of every paste in the heap, entries for any other one are superseded
+  scheduled: HashMap<String, i64>,
+  updates: Receiver<(String, i64)>,
+  store: SharedPasteStore,
+  index: SharedMetadataIndex,
+}
+
+impl ExpiryScheduler {
+  /// Schedules every paste in the index and starts deleting them as they expire, the ones
+  /// that expired while the service was down right away.
+  pub fn start(
+    store: SharedPasteStore,
+    index: SharedMetadataIndex,
+  ) -> Result<JoinHandle<()>, Box<dyn Error>> {
+    // listening before reading the schedule, so nothing inserted in between is missed
+    let (sender, updates) = mpsc::channel();
+    index.set_expiry_listener(sender);
+
+    let mut scheduler = ExpiryScheduler {
+      heap: BinaryHeap::new(),
+      scheduled: HashMap::new(),
+      updates,
+      store,
+      index,
+    };
+    for (key, expires_at) in scheduler.index.expiry_schedule()? {
+      scheduler.schedule(key, expires_at);
+    }
+
+    Ok(thread::spawn(move || scheduler.run()))
+  }
+
+  fn schedule(&mut self, key: String, expires_at: i64) {
+    if self.scheduled.insert(key.clone(), expires_at) == Some(expires_at) {
+      return;
+    }
+    self.heap.push(Reverse((expires_at, key)));
+
+    if self.heap.len() > 2 * self.scheduled.len() {
+      self.heap = self
+        .scheduled
+        .iter()
+        .map(|(key, expires_at)| Reverse((*expires_at, key.clone())))
+        .collect();
+    }
+  }
+
+  fn run(&mut self) {
+    loop {
+      let now = Utc::now().timestamp();
+      self.expire_due(now);
+
+      let wait = match self.heap.peek() {
+        Some(Reverse((expires_at, _))) => Duration::from_secs((expires_at - now).max(1) as u64),
+        None => IDLE_WAIT,
+      };
+
+      match self.updates.recv_timeout(wait) {
+        Ok((key, expires_at)) => {
+          self.schedule(key, expires_at);
+          while let Ok((key, expires_at)) = self.updates.try_recv() {
+            self.schedule(key, expires_at);
+          }
+        }
+        Err(RecvTimeoutError::Timeout) => {}
+        Err(RecvTimeoutError::Disconnected) => return,
+      }
+    }
+  }
+
+  /// deletes every paste due as of the given unix timestamp
+  fn expire_due(&mut self, now: i64) {
+    while matches!(self.heap.peek(), Some(Reverse((expires_at, _))) if *expires_at <= now) {
+      let Reverse((expires_at, key)) = self.heap.pop().unwrap();
+      if self.scheduled.get(&key) != Some(&expires_at) {
+        continue;
+      }
+      self.scheduled.remove(&key);
+
+      if self.store.is_shared() {
+        match self.expiry_in_store(&key, now) {
+          Ok(Some(later)) if later > now => continue,
+          Ok(_) => {}
+          Err(e) => {
+            println!(
+              "Error while checking the shared record of {}. Error: {}",
+              key, e
+            );
+            self.schedule(key, now + RETRY_IN);
+            continue;
+          }
+        }
+      }
+
+      // the record goes first, a crash in between leaves an orphan that gets cleaned up
+      // on startup instead of a record without content
+      match self.index.remove_if_expired(&key, now) {
+        Ok(true) => {}
+        Ok(false) => continue,
+        Err(e) => {
+          println!("Error while expiring the record of {}. Error: {}", key, e);
+          continue;
+        }
+      }
+
+      if let Err(e) = self.store.delete(&key) {
+        println!(
+          "Error while deleting the expired paste {}. Error: {}",
+          key, e
+        );
+      }
+    }
+  }
+
+  /// The expiry (unix timestamp) of the store's copy of the record, `None` when it's gone
+  /// along with the content. One later than `now` is indexed and scheduled again.
+  fn expiry_in_store(&self, key: &str, now: i64) -> Result<Option<i64>, Box<dyn Error>> {
+    let record = match self.store.get_record(key)? {
+      Some(record) if self.store.exists(key) => record,
+      _ => return Ok(None),
+    };
+
+    let expires_at = match record.permanent {
+      true => i64::MAX,
+      false => record.created_timestamp() + record.expiry as i64,
+    };
+    if expires_at > now {
+      record.log(&self.index)?;
+    }
+
+    Ok(Some(expires_at))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::core::Record;
+  use crate::index::MetadataIndex;
+  use crate::s3::tests::fake_store;
+  use crate::store::MemoryStore;
+  use std::sync::Arc;
+
+  /// a scheduler that's only run by hand
+  fn scheduler(store: SharedPasteStore, index: SharedMetadataIndex) -> ExpiryScheduler {
+    let (_sender, updates) = mpsc::channel();
+    ExpiryScheduler {
+      heap: BinaryHeap::new(),
+      scheduled: HashMap::new(),
+      updates,
+      store,
+      index,
+    }
+  }
+
+  #[test]
+  fn test_expired_pastes_are_deleted() {
+    let store: SharedPasteStore = Arc::new(MemoryStore::new());
+    let index: SharedMetadataIndex = Arc::new(MetadataIndex::open_in_memory().unwrap());
+
+    let mut extended = Record::new("late".to_string(), 1);
+    for record in [
+      Record::new("soon".to_string(), 1),
+      Record::new("keep".to_string(), 60),
+    ] {
+      store.put(&record.key, b"hello").unwrap();
+      index.insert(&record).unwrap();
+    }
+    store.put("late", b"hello").unwrap();
+    index.insert(&extended).unwrap();
+
+    ExpiryScheduler::start(store.clone(), index.clone()).unwrap();
+    extended.extend(&index, 60).unwrap();
+    thread::sleep(Duration::from_millis(2_500));
+
+    assert!(!store.exists("soon"));
+    assert!(!index.contains("soon").unwrap());
+    assert!(store.exists("keep"));
+    assert!(store.exists("late"));
+    assert!(index.contains("late").unwrap());
+  }
+
+  #[test]
+  fn test_superseded_entries_are_dropped() {
+    let mut scheduler = scheduler(
+      Arc::new(MemoryStore::new()),
+      Arc::new(MetadataIndex::open_in_memory().unwrap()),
+    );
+
+    for expires_at in 0..1_000 {
+      scheduler.schedule("slid".to_string(), expires_at);
+      scheduler.schedule("kept".to_string(), 500);
+    }
+    assert!(scheduler.heap.len() <= 4);
+
+    scheduler.expire_due(2_000);
+    assert!(scheduler.heap.is_empty());
+    assert!(scheduler.scheduled.is_empty());
+  }
+
+  #[test]
+  fn test_pastes_extended_by_another_instance_are_kept() {
+    let store: SharedPasteStore = Arc::new(fake_store());
+    let ours: SharedMetadataIndex = Arc::new(MetadataIndex::open_in_memory().unwrap());
+    let theirs = MetadataIndex::open_in_memory().unwrap();
+
+    let mut record = Record::new("abcd".to_string(), 60);
+    store.put("abcd", b"hello").unwrap();
+    store.put_record(&record).unwrap();
+    record.log(&ours).unwrap();
+    record.log(&theirs).unwrap();
+
+    // extended through the other instance
+    record.extend(&theirs, 3_600).unwrap();
+    store.put_record(&record).unwrap();
+
+    let mut scheduler = scheduler(store.clone(), ours.clone());
+    let expires_at = record.created_timestamp() + 60;
+    scheduler.schedule("abcd".to_string(), expires_at);
+    scheduler.expire_due(expires_at);
+
+    assert!(store.exists("abcd"));
+    assert_eq!(60 + 3_600, ours.get("abcd").unwrap().unwrap().expiry);
+
+    scheduler.schedule("abcd".to_string(), expires_at + 3_600);
+    scheduler.expire_due(expires_at + 3_600);
+    assert!(!store.exists("abcd"));
+    assert!(!ours.contains("abcd").unwrap());
+  }
+}
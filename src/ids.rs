@@ -0,0 +1,762 @@
+extern crate bloom;
+
+use crate::handle_err;
+use crate::index::SharedMetadataIndex;
+use crate::param_guards::Slug;
+use crate::store::{id_marker, SharedPasteStore};
+use bloom::BloomFilter;
+use chrono::Utc;
+use rand::{self, Rng};
+use rocket::http::Status;
+use rocket::tokio::runtime::Handle;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Headers the upload routes used to take the allocated ID and the cache clearing from. Clients
+/// sending them get a 400, see `request_guards::UploadRequestGuard`.
+pub const RESERVED_HEADERS: [&str; 2] = ["unique-pastebin-id", "time-to-clear-expired-keys"];
+
+/// An ID reserved for one upload. Dropped before `keep`, the reservation is let go, so IDs
+/// of requests that never got to store anything are free again right away.
+pub struct IdReservation {
+  id: String,
+  allocator: Arc<UniqueID>,
+  kept: bool,
+}
+
+impl IdReservation {
+  pub fn id(&self) -> &str {
+    &self.id
+  }
+
+  /// the paste is stored under the ID, its record holds on to it from here on. Uploads that
+  /// fail just drop the reservation
+  pub fn keep(mut self) -> String {
+    self.kept = true;
+    std::mem::take(&mut self.id)
+  }
+}
+
+impl fmt::Debug for IdReservation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_tuple("IdReservation").field(&self.id).finish()
+  }
+}
+
+impl Drop for IdReservation {
+  fn drop(&mut self) {
+    if self.kept {
+      return;
+    }
+
+    let allocator = self.allocator.clone();
+    let id = std::mem::take(&mut self.id);
+    // a shared store is a round trip away, the index alone is released right away so the ID
+    // can be asked for again by the next request
+    match Handle::try_current() {
+      Ok(handle) if allocator.store.is_shared() => {
+        drop(handle.spawn_blocking(move || allocator.release(&id)))
+      }
+      _ => allocator.release(&id),
+    }
+  }
+}
+
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Crockford's base 32, without the I, L, O and U easily mistaken for 1, 0 and V
+const CROCKFORD32: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// the words of `IdAlphabet::Words` IDs, one per line, lowercase letters only
+const WORDLIST: &str = include_str!("wordlist.txt");
+
+/// What random IDs are made of. The length of `Words` IDs counts words, like the three of
+/// `brave-otter-mango`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdAlphabet {
+  Base62,
+  Crockford32,
+  Words,
+}
+
+impl IdAlphabet {
+  /// value => `base62` | `crockford32` | `words`
+  pub fn parse(value: &str) -> Result<Self, String> {
+    match value {
+      "base62" => Ok(IdAlphabet::Base62),
+      "crockford32" => Ok(IdAlphabet::Crockford32),
+      "words" => Ok(IdAlphabet::Words),
+      _ => Err(format!("unknown ID alphabet ({})", value)),
+    }
+  }
+
+  /// `None` for `Words`
+  fn chars(&self) -> Option<&'static [u8]> {
+    match self {
+      IdAlphabet::Base62 => Some(BASE62),
+      IdAlphabet::Crockford32 => Some(CROCKFORD32),
+      IdAlphabet::Words => None,
+    }
+  }
+
+  fn words() -> Vec<&'static str> {
+    WORDLIST.lines().filter(|word| !word.is_empty()).collect()
+  }
+
+  /// number of characters, or words, an ID picks from
+  fn symbols(&self) -> usize {
+    match self.chars() {
+      Some(chars) => chars.len(),
+      None => IdAlphabet::words().len(),
+    }
+  }
+
+  pub fn default_length(&self) -> usize {
+    match self {
+      IdAlphabet::Words => 3,
+      _ => 4,
+    }
+  }
+
+  /// longest the IDs can grow and still pass `param_guards::ID`, 5 words of up to 8 letters
+  fn max_length(&self) -> usize {
+    match self {
+      IdAlphabet::Words => 5,
+      _ => Slug::MAX_LENGTH,
+    }
+  }
+}
+
+/// How random IDs are generated, see `CustomConfig::ids`.
+#[derive(Debug, Clone)]
+pub struct IdConfig {
+  /// length of the IDs to start with, it grows as they run out
+  pub length: usize,
+  pub alphabet: IdAlphabet,
+  /// number of IDs the Bloom filters are sized for
+  pub expected_items: u32,
+  pub false_positive_rate: f32,
+  /// share of the IDs of the current length in use past which the length grows by one
+  pub grow_at: f64,
+}
+
+impl IdConfig {
+  /// checks the configured values, so a bad one fails at startup instead of with the first
+  /// upload
+  pub fn validate(&self) -> Result<(), String> {
+    let max_length = self.alphabet.max_length();
+    if self.length == 0 || self.length > max_length {
+      return Err(format!(
+        "the ID length has to be between 1 and {} for the {} alphabet, not {}",
+        max_length,
+        format!("{:?}", self.alphabet).to_lowercase(),
+        self.length
+      ));
+    }
+
+    if !(self.grow_at > 0.0 && self.grow_at < 1.0) {
+      return Err(format!(
+        "the occupancy the IDs grow at has to be between 0 and 1, not {}",
+        self.grow_at
+      ));
+    }
+
+    if !(self.false_positive_rate > 0.0 && self.false_positive_rate < 1.0) {
+      return Err(format!(
+        "the false positive rate of the Bloom filters has to be between 0 and 1, not {}",
+        self.false_positive_rate
+      ));
+    }
+
+    if self.expected_items == 0 {
+      return Err("the Bloom filters have to expect at least one ID".to_string());
+    }
+
+    Ok(())
+  }
+}
+
+impl Default for IdConfig {
+  fn default() -> Self {
+    IdConfig {
+      length: 4,
+      alphabet: IdAlphabet::Base62,
+      expected_items: 1_606_208,
+      false_positive_rate: 0.01,
+      grow_at: 0.5,
+    }
+  }
+}
+
+/// random IDs in a row found taken before the length grows regardless of the occupancy, or
+/// before giving up when it can't grow any longer
+const MAX_ATTEMPTS: usize = 64;
+
+/// Every random ID tried was taken and the length can't grow any longer. Uploads get a 503.
+#[derive(Debug)]
+pub struct IdsExhausted {
+  pub length: usize,
+}
+
+impl fmt::Display for IdsExhausted {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "no free ID found in {} attempts, the IDs of length {} are running out",
+      MAX_ATTEMPTS, self.length
+    )
+  }
+}
+
+impl Error for IdsExhausted {}
+
+/// How full the space of random IDs is, shared by `UniqueID` and `GET /stats`.
+#[derive(Debug)]
+pub struct IdCapacity {
+  alphabet: IdAlphabet,
+  symbols: usize,
+  length: AtomicUsize,
+  /// `IdAlphabet::max_length`, the length doesn't grow past it
+  max_length: usize,
+  grow_at: f64,
+  /// times the length grew since startup
+  grown: AtomicUsize,
+}
+
+/// The `ids` part of `GET /stats`.
+#[derive(Debug, Serialize)]
+pub struct IdStats {
+  pub length: usize,
+  pub alphabet: IdAlphabet,
+  /// share of the IDs of the current length in use, counting every stored paste
+  pub occupancy: f64,
+  pub grow_at: f64,
+  pub grown: usize,
+  /// the length can't grow any longer and the occupancy is past `grow_at`, uploads start
+  /// failing with a 503 once random IDs keep coming up taken
+  pub near_capacity: bool,
+}
+
+impl IdCapacity {
+  pub fn length(&self) -> usize {
+    self.length.load(Ordering::SeqCst)
+  }
+
+  pub fn occupancy(&self, pastes: u64) -> f64 {
+    pastes as f64 / (self.symbols as f64).powi(self.length() as i32)
+  }
+
+  /// Grows the length by one, unless someone else grew it past `from` already.
+  fn grow(&self, from: usize, reason: &str) {
+    if from >= self.max_length {
+      println!(
+        "Warning: the IDs of length {} are running out ({}), and can't grow any longer",
+        from, reason
+      );
+      return;
+    }
+
+    let grown = self
+      .length
+      .compare_exchange(from, from + 1, Ordering::SeqCst, Ordering::SeqCst)
+      .is_ok();
+
+    if grown {
+      self.grown.fetch_add(1, Ordering::SeqCst);
+      println!(
+        "Warning: the IDs of length {} are running out ({}), new ones are {} long",
+        from,
+        reason,
+        from + 1
+      );
+    }
+  }
+
+  /// grows the length until the given number of pastes fills less than `grow_at` of it
+  fn grow_for(&self, pastes: u64) {
+    loop {
+      let length = self.length();
+      let occupancy = self.occupancy(pastes);
+      if occupancy <= self.grow_at || length >= self.max_length {
+        return;
+      }
+
+      self.grow(
+        length,
+        &format!("{:.1}% of them in use", occupancy * 100_f64),
+      );
+    }
+  }
+
+  pub fn stats(&self, pastes: u64) -> IdStats {
+    let length = self.length();
+    let occupancy = self.occupancy(pastes);
+
+    IdStats {
+      length,
+      alphabet: self.alphabet,
+      occupancy,
+      grow_at: self.grow_at,
+      grown: self.grown.load(Ordering::SeqCst),
+      near_capacity: length >= self.max_length && occupancy > self.grow_at,
+    }
+  }
+}
+
+/// number of Bloom filters the IDs are spread over, so concurrent uploads rarely wait on
+/// each other
+const SHARDS: usize = 16;
+
+/// Hands out the IDs of new pastes, to `request_guards::UploadRequestGuard` of the upload
+/// routes.
+///
+/// The Bloom filters only rule out IDs known to be taken without a trip to the metadata
+/// index, `MetadataIndex::reserve_id` has the final say. On storage shared with other
+/// instances, so does the ID's marker, see `PasteStore::create_marker`. A false negative
+/// of a filter, or one being rebuilt meanwhile, costs a retry and never a collision.
+pub struct UniqueID {
+  shards: Vec<Mutex<BloomFilter>>,
+  store: SharedPasteStore,
+  index: SharedMetadataIndex,
+  capacity: Arc<IdCapacity>,
+  /// the bundled wordlist for `IdAlphabet::Words`, empty otherwise
+  words: Vec<&'static str>,
+  upload_counter: AtomicUsize,
+}
+
+const MAX_CACHE_KEYS_TO_RETAIN: usize = 500;
+
+impl UniqueID {
+  pub fn new(store: SharedPasteStore, index: SharedMetadataIndex, config: &IdConfig) -> UniqueID {
+    let per_shard = (config.expected_items / SHARDS as u32).max(1);
+    let shards = (0..SHARDS)
+      .map(|_| {
+        Mutex::new(BloomFilter::with_rate(
+          config.false_positive_rate,
+          per_shard,
+        ))
+      })
+      .collect();
+
+    let words = match config.alphabet {
+      IdAlphabet::Words => IdAlphabet::words(),
+      _ => Vec::new(),
+    };
+
+    let capacity = IdCapacity {
+      alphabet: config.alphabet,
+      symbols: config.alphabet.symbols(),
+      length: AtomicUsize::new(config.length),
+      max_length: config.alphabet.max_length(),
+      grow_at: config.grow_at,
+      grown: AtomicUsize::new(0),
+    };
+
+    let unique_id = UniqueID {
+      shards,
+      store,
+      index,
+      capacity: Arc::new(capacity),
+      words,
+      upload_counter: AtomicUsize::new(1),
+    };
+
+    let total_uploads_count = unique_id.load_stored_ids();
+
+    if total_uploads_count > 0 {
+      println!("Loaded {} keys to Bloom filter!", total_uploads_count);
+    }
+    unique_id.check_capacity();
+
+    unique_id
+  }
+
+  pub fn capacity(&self) -> Arc<IdCapacity> {
+    self.capacity.clone()
+  }
+
+  /// grows the ID length when the stored pastes fill too much of it
+  fn check_capacity(&self) {
+    let pastes = self.index.count();
+    handle_err!(pastes, "error trying to count the stored pastes", {
+      return;
+    });
+
+    self.capacity.grow_for(pastes.unwrap());
+  }
+
+  fn shard(&self, id: &str) -> MutexGuard<'_, BloomFilter> {
+    let mut hasher = DefaultHasher::new();
+    id.hash(&mut hasher);
+
+    self.shards[hasher.finish() as usize % SHARDS]
+      .lock()
+      .expect("bloom filter lock was poisoned")
+  }
+
+  /// inserts the ID of every paste in the metadata index into the filters and returns the
+  /// count. Pastes other instances sharing the store uploaded aren't in there, their ID
+  /// markers rule those out
+  fn load_stored_ids(&self) -> u32 {
+    let ids = self.index.ids();
+    handle_err!(ids, "error trying to read the IDs of the stored pastes", {
+      return 0;
+    });
+
+    let mut counter: u32 = 0;
+    for id in ids.unwrap() {
+      self.shard(&id).insert(&id);
+      counter += 1;
+    }
+
+    counter
+  }
+
+  /// Counts an upload. Every `MAX_CACHE_KEYS_TO_RETAIN`th one returns `true`, it's time to
+  /// clear the expired keys from the cache and to `reload` the filters.
+  pub fn count_upload(&self) -> bool {
+    self
+      .upload_counter
+      .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+        match count >= MAX_CACHE_KEYS_TO_RETAIN {
+          true => Some(0),
+          false => Some(count + 1),
+        }
+      })
+      .map(|count| count >= MAX_CACHE_KEYS_TO_RETAIN)
+      .unwrap_or(false)
+  }
+
+  /// Empties the filters and loads them again, forgetting the IDs of expired pastes. Reads
+  /// every ID in the index, so it's kept off the request path.
+  pub fn reload(&self) {
+    for shard in &self.shards {
+      shard
+        .lock()
+        .expect("bloom filter lock was poisoned")
+        .clear();
+    }
+
+    self.load_stored_ids();
+    self.check_capacity();
+  }
+
+  pub fn generate_id(&self, length: usize) -> String {
+    let chars = match self.capacity.alphabet.chars() {
+      Some(chars) => chars,
+      None => return self.generate_word_id(length),
+    };
+    let mut id_str = String::with_capacity(length);
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..length {
+      id_str.push(chars[rng.gen_range(0..chars.len())] as char);
+    }
+
+    id_str
+  }
+
+  /// `length` words from the wordlist, hyphenated => `brave-otter-mango`
+  pub fn generate_word_id(&self, length: usize) -> String {
+    let mut rng = rand::thread_rng();
+
+    (0..length)
+      .map(|_| self.words[rng.gen_range(0..self.words.len())])
+      .collect::<Vec<_>>()
+      .join("-")
+  }
+
+  /// Reserves the ID in the index, and on shared storage with its marker too. Returns
+  /// `false` when a paste or another upload, of any instance, has it.
+  fn reserve(&self, id: &str) -> Result<bool, Box<dyn Error>> {
+    if !self.index.reserve_id(id, Utc::now().timestamp())? {
+      return Ok(false);
+    }
+
+    if self.store.is_shared() && !self.store.create_marker(&id_marker(id))? {
+      self.index.release_id(id)?;
+      return Ok(false);
+    }
+
+    Ok(true)
+  }
+
+  /// lets go of a reservation of an upload that didn't happen, see `IdReservation`
+  fn release(&self, id: &str) {
+    handle_err!(
+      self.index.release_id(id),
+      "error trying to release the reservation of an ID",
+      {}
+    );
+
+    if self.store.is_shared() {
+      handle_err!(
+        self.store.delete_marker(&id_marker(id)),
+        "error trying to release the marker of an ID",
+        {}
+      );
+    }
+  }
+
+  /// Reserves the given slug for this upload, unless a paste or another upload has it.
+  /// Returns `false` when taken.
+  pub fn allocate_slug(&self, slug: &Slug) -> Result<bool, Box<dyn Error>> {
+    let id = &slug.0;
+    // pastes stored before IDs got markers
+    if self.store.is_shared() && self.store.exists(id) {
+      return Ok(false);
+    }
+
+    // unlike random IDs the filters don't get a say, their false positives would turn away
+    // slugs nobody has
+    let reserved = self.reserve(id)?;
+    if reserved {
+      self.shard(id).insert(id);
+    }
+
+    Ok(reserved)
+  }
+
+  /// The ID for an upload, the slug it asks for or a random one. Blocks on the index and, on
+  /// shared storage, on the store.
+  pub fn allocate_for(
+    allocator: Arc<UniqueID>,
+    slug: Option<Slug>,
+  ) -> Result<IdReservation, (Status, String)> {
+    let allocated = match &slug {
+      Some(slug) => allocator
+        .allocate_slug(slug)
+        .map(|reserved| match reserved {
+          true => Ok(slug.0.clone()),
+          false => Err((Status::Conflict, format!("`slug` ({}) is taken", slug.0))),
+        }),
+      None => allocator.allocate().map(Ok),
+    };
+
+    let id = allocated.unwrap_or_else(|e| {
+      println!("error trying to reserve an ID for an upload. Error: {}", e);
+      match e.is::<IdsExhausted>() {
+        true => Err((Status::ServiceUnavailable, e.to_string())),
+        false => Err((Status::InternalServerError, e.to_string())),
+      }
+    })?;
+
+    Ok(IdReservation {
+      id,
+      allocator,
+      kept: false,
+    })
+  }
+
+  /// Loops through random IDs until one is reserved in the metadata index for this upload.
+  /// The length grows when that takes too many attempts, the occupancy only gets checked
+  /// every so often. Gives up with `IdsExhausted` when it can't grow any longer.
+  pub fn allocate(&self) -> Result<String, Box<dyn Error>> {
+    let mut attempts = 0;
+    loop {
+      let length = self.capacity.length();
+      let id = self.generate_id(length);
+
+      let taken = self.shard(&id).contains(&id)
+        // pastes other instances stored before IDs got markers don't show up anywhere else
+        || (self.store.is_shared() && self.store.exists(&id));
+
+      if !taken && self.reserve(&id)? {
+        self.shard(&id).insert(&id);
+        return Ok(id);
+      }
+
+      attempts += 1;
+      if attempts == MAX_ATTEMPTS {
+        if length >= self.capacity.max_length {
+          return Err(IdsExhausted { length }.into());
+        }
+
+        self
+          .capacity
+          .grow(length, &format!("{} taken in a row", MAX_ATTEMPTS));
+        attempts = 0;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::core::Record;
+  use crate::index::MetadataIndex;
+  use crate::store::MemoryStore;
+  use std::collections::HashSet;
+  use std::sync::Arc;
+  use std::thread;
+
+  #[test]
+  fn test_concurrent_allocations_never_collide() {
+    let index: SharedMetadataIndex = Arc::new(MetadataIndex::open_in_memory().unwrap());
+    // one character long, so the 62 IDs there are get fought over
+    let config = IdConfig {
+      length: 1,
+      expected_items: 62,
+      grow_at: 1.0,
+      ..IdConfig::default()
+    };
+    let unique_id = Arc::new(UniqueID::new(Arc::new(MemoryStore::new()), index, &config));
+
+    let handles: Vec<_> = (0..8)
+      .map(|_| {
+        let unique_id = unique_id.clone();
+        thread::spawn(move || {
+          (0..7)
+            .map(|_| unique_id.allocate().unwrap())
+            .collect::<Vec<_>>()
+        })
+      })
+      .collect();
+
+    let ids: HashSet<String> = handles
+      .into_iter()
+      .flat_map(|handle| handle.join().unwrap())
+      .collect();
+    assert_eq!(56, ids.len());
+  }
+
+  #[test]
+  fn test_unused_reservations_are_released() {
+    let index: SharedMetadataIndex = Arc::new(MetadataIndex::open_in_memory().unwrap());
+    let unique_id = Arc::new(UniqueID::new(
+      Arc::new(MemoryStore::new()),
+      index.clone(),
+      &IdConfig::default(),
+    ));
+
+    let slug = Slug::parse("deploy-checklist").unwrap();
+    let reservation = UniqueID::allocate_for(unique_id.clone(), Some(slug)).unwrap();
+    let taken = UniqueID::allocate_for(
+      unique_id.clone(),
+      Some(Slug::parse("deploy-checklist").unwrap()),
+    );
+    assert_eq!(Status::Conflict, taken.unwrap_err().0);
+
+    drop(reservation);
+    let reservation = UniqueID::allocate_for(
+      unique_id.clone(),
+      Some(Slug::parse("deploy-checklist").unwrap()),
+    )
+    .unwrap();
+
+    // a kept one stays taken until the paste's record takes over
+    let id = reservation.keep();
+    assert!(!index.reserve_id(&id, Utc::now().timestamp()).unwrap());
+  }
+
+  #[test]
+  fn test_length_grows_with_occupancy() {
+    let index: SharedMetadataIndex = Arc::new(MetadataIndex::open_in_memory().unwrap());
+    for key in ["A", "B"] {
+      index.insert(&Record::new(key.to_string(), 60)).unwrap();
+    }
+
+    let config = IdConfig {
+      length: 1,
+      alphabet: IdAlphabet::Crockford32,
+      grow_at: 0.05,
+      ..IdConfig::default()
+    };
+    let unique_id = UniqueID::new(Arc::new(MemoryStore::new()), index, &config);
+
+    // loaded from the index, the store has none of them
+    assert!(unique_id.shard("A").contains(&"A"));
+
+    // 2 of 32 is past 5%, 2 of 1024 isn't
+    let capacity = unique_id.capacity();
+    assert_eq!(2, capacity.length());
+    assert_eq!(1, capacity.stats(2).grown);
+
+    let id = unique_id.allocate().unwrap();
+    assert_eq!(2, id.len());
+    assert!(id.bytes().all(|c| CROCKFORD32.contains(&c)));
+  }
+
+  #[test]
+  fn test_allocation_gives_up_at_the_longest_length() {
+    let index: SharedMetadataIndex = Arc::new(MetadataIndex::open_in_memory().unwrap());
+    let config = IdConfig {
+      length: 1,
+      alphabet: IdAlphabet::Crockford32,
+      ..IdConfig::default()
+    };
+    let mut unique_id = UniqueID::new(Arc::new(MemoryStore::new()), index.clone(), &config);
+    // as if one character was as long as the IDs could get
+    Arc::get_mut(&mut unique_id.capacity).unwrap().max_length = 1;
+
+    for c in CROCKFORD32 {
+      let id = (*c as char).to_string();
+      assert!(index.reserve_id(&id, Utc::now().timestamp()).unwrap());
+    }
+
+    let e = unique_id.allocate().unwrap_err();
+    assert!(e.is::<IdsExhausted>());
+    assert_eq!(1, unique_id.capacity.length());
+    assert!(unique_id.capacity.stats(32).near_capacity);
+    assert!(!unique_id.capacity.stats(8).near_capacity);
+  }
+
+  #[test]
+  fn test_id_config_validation() {
+    assert!(IdConfig::default().validate().is_ok());
+    for config in [
+      IdConfig {
+        length: 0,
+        ..IdConfig::default()
+      },
+      IdConfig {
+        length: 49,
+        ..IdConfig::default()
+      },
+      IdConfig {
+        grow_at: 1.0,
+        ..IdConfig::default()
+      },
+      IdConfig {
+        false_positive_rate: 0.0,
+        ..IdConfig::default()
+      },
+      IdConfig {
+        expected_items: 0,
+        ..IdConfig::default()
+      },
+    ] {
+      assert!(config.validate().is_err(), "{:?}", config);
+    }
+  }
+
+  #[test]
+  fn test_word_ids() {
+    let words = IdAlphabet::words();
+    assert!(words
+      .iter()
+      .all(|word| word.len() <= 8 && word.bytes().all(|c| c.is_ascii_lowercase())));
+
+    let index: SharedMetadataIndex = Arc::new(MetadataIndex::open_in_memory().unwrap());
+    let config = IdConfig {
+      length: IdAlphabet::Words.default_length(),
+      alphabet: IdAlphabet::Words,
+      ..IdConfig::default()
+    };
+    let unique_id = UniqueID::new(Arc::new(MemoryStore::new()), index.clone(), &config);
+
+    let id = unique_id.allocate().unwrap();
+    let parts: Vec<&str> = id.split('-').collect();
+    assert_eq!(3, parts.len());
+    assert!(parts.iter().all(|part| words.contains(part)));
+    // reserved like any other ID
+    assert!(!index.reserve_id(&id, Utc::now().timestamp()).unwrap());
+  }
+}
@@ -0,0 +1,923 @@
+use crate::core::{Record, DEFAULT_MAX_RETENTION};
+use crate::store::PasteStore;
+use crate::util::SIMPLE_DATE_FORMAT;
+use chrono::{NaiveDate, TimeZone, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Each entry is applied once, in order, and tracked through `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &["
+  CREATE TABLE pastes (
+    id TEXT PRIMARY KEY,
+    created_at INTEGER NOT NULL,
+    -- NULL for permanent pastes
+    expires_at INTEGER,
+    size INTEGER NOT NULL DEFAULT 0,
+    content_type TEXT,
+    client_encrypted INTEGER NOT NULL DEFAULT 0,
+    burn_after_reading INTEGER NOT NULL DEFAULT 0,
+    consumed INTEGER NOT NULL DEFAULT 0,
+    remaining_views INTEGER,
+    idle_window INTEGER,
+    last_access INTEGER,
+    expiry_ceiling INTEGER,
+    -- `size`, unless the content is a deduplicated blob counted with the blob
+    owned_bytes INTEGER NOT NULL DEFAULT 0
+  );
+  CREATE INDEX pastes_expires_at ON pastes (expires_at);
+  CREATE TABLE blobs (
+    digest TEXT PRIMARY KEY,
+    refcount INTEGER NOT NULL,
+    size INTEGER NOT NULL DEFAULT 0
+  );
+  CREATE TABLE paste_blobs (
+    id TEXT PRIMARY KEY,
+    digest TEXT NOT NULL REFERENCES blobs (digest)
+  );
+  CREATE TABLE id_reservations (
+    id TEXT PRIMARY KEY,
+    reserved_at INTEGER NOT NULL
+  );
+"];
+
+/// RESERVATION_TTL = 3_600 => seconds for an hour, after which the reservation of an upload
+/// that never got logged is let go
+const RESERVATION_TTL: i64 = 3_600;
+
+const RECORD_COLUMNS: &str =
+  "id, created_at, expires_at, size, content_type, client_encrypted, burn_after_reading, \
+   remaining_views, idle_window, last_access, expiry_ceiling";
+
+/// Embedded SQLite index holding the metadata (`core::Record`) of every paste.
+pub struct MetadataIndex {
+  conn: Mutex<Connection>,
+  /// bytes taken up in the store, kept up to date on insert and removal instead of being
+  /// recomputed by every upload. A paste counts its own `size`, unless its content is a
+  /// deduplicated blob, which is counted once when created and freed with its last link.
+  used_bytes: AtomicU64,
+  /// told the key and new expiry (unix timestamp) of every record inserted or rescheduled,
+  /// see `expiry::ExpiryScheduler`
+  expiry_listener: Mutex<Option<Sender<(String, i64)>>>,
+}
+
+pub type SharedMetadataIndex = Arc<MetadataIndex>;
+
+impl MetadataIndex {
+  /// path => `index/pastes.db` | `:memory:` keeps the index in memory only
+  pub fn open(path: &str) -> Result<Self, Box<dyn Error>> {
+    let conn = if path == ":memory:" {
+      Connection::open_in_memory()?
+    } else {
+      if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+      }
+      Connection::open(path)?
+    };
+
+    MetadataIndex::with_connection(conn)
+  }
+
+  pub fn open_in_memory() -> Result<Self, Box<dyn Error>> {
+    MetadataIndex::with_connection(Connection::open_in_memory()?)
+  }
+
+  /// Applies the migrations the database hasn't seen yet. Each one commits together with its
+  /// `user_version` bump, so a migration failing halfway leaves nothing of it behind and is
+  /// retried as a whole on the next start.
+  fn migrate(conn: &mut Connection, migrations: &[&str]) -> rusqlite::Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    for (n, migration) in migrations.iter().enumerate().skip(version) {
+      let tx = conn.transaction()?;
+      tx.execute_batch(migration)?;
+      tx.pragma_update(None, "user_version", (n + 1) as i64)?;
+      tx.commit()?;
+    }
+
+    Ok(())
+  }
+
+  fn with_connection(mut conn: Connection) -> Result<Self, Box<dyn Error>> {
+    MetadataIndex::migrate(&mut conn, MIGRATIONS)?;
+
+    let used_bytes: i64 = conn.query_row(
+      "SELECT (SELECT COALESCE(SUM(owned_bytes), 0) FROM pastes)
+         + (SELECT COALESCE(SUM(size), 0) FROM blobs)",
+      [],
+      |row| row.get(0),
+    )?;
+
+    Ok(MetadataIndex {
+      conn: Mutex::new(conn),
+      used_bytes: AtomicU64::new(used_bytes as u64),
+      expiry_listener: Mutex::new(None),
+    })
+  }
+
+  fn conn(&self) -> MutexGuard<'_, Connection> {
+    self.conn.lock().expect("metadata index lock was poisoned")
+  }
+
+  pub fn set_expiry_listener(&self, listener: Sender<(String, i64)>) {
+    *self
+      .expiry_listener
+      .lock()
+      .expect("expiry listener lock was poisoned") = Some(listener);
+  }
+
+  fn notify_expiry(&self, key: &str, expires_at: i64) {
+    let listener = self
+      .expiry_listener
+      .lock()
+      .expect("expiry listener lock was poisoned");
+    if let Some(listener) = listener.as_ref() {
+      // the listener only goes away with the process
+      let _ = listener.send((key.to_string(), expires_at));
+    }
+  }
+
+  fn adjust_used_bytes(&self, delta: i64) {
+    if delta >= 0 {
+      self.used_bytes.fetch_add(delta as u64, Ordering::SeqCst);
+    } else {
+      self
+        .used_bytes
+        .fetch_sub(delta.unsigned_abs(), Ordering::SeqCst);
+    }
+  }
+
+  /// total size of the stored pastes, in bytes
+  pub fn used_bytes(&self) -> u64 {
+    self.used_bytes.load(Ordering::SeqCst)
+  }
+
+  pub fn count(&self) -> rusqlite::Result<u64> {
+    self
+      .conn()
+      .query_row("SELECT COUNT(*) FROM pastes", [], |row| {
+        row.get::<_, i64>(0)
+      })
+      .map(|count| count as u64)
+  }
+
+  fn record_from_row(row: &Row) -> rusqlite::Result<Record> {
+    let created_at: i64 = row.get(1)?;
+    let expires_at: Option<i64> = row.get(2)?;
+
+    Ok(Record {
+      key: row.get(0)?,
+      expiry: expires_at.map_or(0, |expires_at| (expires_at - created_at).max(0) as u64),
+      created_time: Utc.timestamp_opt(created_at, 0).unwrap(),
+      size: row.get::<_, i64>(3)? as u64,
+      content_type: row.get(4)?,
+      client_encrypted: row.get(5)?,
+      burn_after_reading: row.get(6)?,
+      remaining_views: row.get::<_, Option<i64>>(7)?.map(|views| views as u64),
+      idle_window: row.get::<_, Option<i64>>(8)?.map(|seconds| seconds as u64),
+      last_access: row.get(9)?,
+      expiry_ceiling: row.get::<_, Option<i64>>(10)?.map(|seconds| seconds as u64),
+      permanent: expires_at.is_none(),
+    })
+  }
+
+  fn query_records(&self, sql: &str, param: &dyn rusqlite::ToSql) -> rusqlite::Result<Vec<Record>> {
+    let conn = self.conn();
+    let mut stmt = conn.prepare(sql)?;
+    let records = stmt
+      .query_map([param], MetadataIndex::record_from_row)?
+      .collect();
+    records
+  }
+
+  /// Permanent records are kept out of the expiry schedule.
+  pub fn insert(&self, record: &Record) -> rusqlite::Result<()> {
+    let created_at = record.created_timestamp();
+    let expires_at = match record.permanent {
+      true => None,
+      false => Some(created_at + record.expiry as i64),
+    };
+    let conn = self.conn();
+    let previous_bytes: i64 = conn
+      .query_row(
+        "SELECT owned_bytes FROM pastes WHERE id = ?1",
+        [&record.key],
+        |row| row.get(0),
+      )
+      .optional()?
+      .unwrap_or(0);
+    // the content of a deduplicated paste was counted with its blob already
+    let deduplicated = conn
+      .query_row(
+        "SELECT 1 FROM paste_blobs WHERE id = ?1",
+        [&record.key],
+        |_| Ok(()),
+      )
+      .optional()?
+      .is_some();
+    let owned_bytes = match deduplicated {
+      true => 0,
+      false => record.size as i64,
+    };
+
+    conn.execute(
+      "INSERT OR REPLACE INTO pastes
+         (id, created_at, expires_at, size, content_type, client_encrypted, burn_after_reading,
+          remaining_views, idle_window, last_access, expiry_ceiling, owned_bytes)
+       VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+      params![
+        record.key,
+        created_at,
+        expires_at,
+        record.size as i64,
+        record.content_type,
+        record.client_encrypted,
+        record.burn_after_reading,
+        record.remaining_views.map(|views| views as i64),
+        record.idle_window.map(|seconds| seconds as i64),
+        record.last_access,
+        record.expiry_ceiling.map(|seconds| seconds as i64),
+        owned_bytes,
+      ],
+    )?;
+    conn.execute("DELETE FROM id_reservations WHERE id = ?1", [&record.key])?;
+
+    self.adjust_used_bytes(owned_bytes - previous_bytes);
+    drop(conn);
+
+    if let Some(expires_at) = expires_at {
+      self.notify_expiry(&record.key, expires_at);
+    }
+    Ok(())
+  }
+
+  pub fn get(&self, key: &str) -> rusqlite::Result<Option<Record>> {
+    self
+      .conn()
+      .query_row(
+        &format!("SELECT {} FROM pastes WHERE id = ?1", RECORD_COLUMNS),
+        [key],
+        MetadataIndex::record_from_row,
+      )
+      .optional()
+  }
+
+  pub fn contains(&self, key: &str) -> rusqlite::Result<bool> {
+    Ok(
+      self
+        .conn()
+        .query_row("SELECT 1 FROM pastes WHERE id = ?1", [key], |_| Ok(()))
+        .optional()?
+        .is_some(),
+    )
+  }
+
+  /// `true` for the tombstone of a paste that was burned or ran out of views, see `claim`
+  pub fn is_tombstone(&self, key: &str) -> rusqlite::Result<bool> {
+    Ok(
+      self
+        .conn()
+        .query_row(
+          "SELECT 1 FROM pastes WHERE id = ?1 AND (consumed = 1 OR remaining_views = 0)",
+          [key],
+          |_| Ok(()),
+        )
+        .optional()?
+        .is_some(),
+    )
+  }
+
+  /// returns `true` when a record was removed
+  pub fn remove(&self, key: &str) -> rusqlite::Result<bool> {
+    let size: Option<i64> = self
+      .conn()
+      .query_row(
+        "DELETE FROM pastes WHERE id = ?1 RETURNING owned_bytes",
+        [key],
+        |row| row.get(0),
+      )
+      .optional()?;
+
+    match size {
+      Some(size) => {
+        self.adjust_used_bytes(-size);
+        Ok(true)
+      }
+      None => Ok(false),
+    }
+  }
+
+  /// Moves the record to its new expiry, along with the state of its sliding expiry. Returns
+  /// `false` when there is no record for the key.
+  pub fn reschedule(&self, record: &Record) -> rusqlite::Result<bool> {
+    let expires_at = record.created_timestamp() + record.expiry as i64;
+    let updated = self.conn().execute(
+      "UPDATE pastes SET expires_at = ?2, last_access = ?3, expiry_ceiling = ?4 WHERE id = ?1",
+      params![
+        record.key,
+        expires_at,
+        record.last_access,
+        record.expiry_ceiling.map(|seconds| seconds as i64),
+      ],
+    )? > 0;
+
+    if updated {
+      self.notify_expiry(&record.key, expires_at);
+    }
+    Ok(updated)
+  }
+
+  /// Claims the ID for an upload about to happen, until its record gets inserted. Returns
+  /// `false` when a paste or another upload has it already, this is the final say on whether
+  /// an ID is free.
+  pub fn reserve_id(&self, id: &str, now: i64) -> rusqlite::Result<bool> {
+    let conn = self.conn();
+    conn.execute(
+      "DELETE FROM id_reservations WHERE reserved_at <= ?1",
+      [now - RESERVATION_TTL],
+    )?;
+
+    let reserved = conn.execute(
+      "INSERT OR IGNORE INTO id_reservations (id, reserved_at)
+       SELECT ?1, ?2 WHERE NOT EXISTS (SELECT 1 FROM pastes WHERE id = ?1)",
+      params![id, now],
+    )?;
+    Ok(reserved > 0)
+  }
+
+  /// lets go of a reservation made by `reserve_id` for an upload that didn't happen
+  pub fn release_id(&self, id: &str) -> rusqlite::Result<()> {
+    self
+      .conn()
+      .execute("DELETE FROM id_reservations WHERE id = ?1", [id])?;
+    Ok(())
+  }
+
+  /// Removes the record if it expired as of the given unix timestamp, it might have been
+  /// rescheduled since its expiry was looked up. Returns `true` when it was removed.
+  pub fn remove_if_expired(&self, key: &str, now: i64) -> rusqlite::Result<bool> {
+    let size: Option<i64> = self
+      .conn()
+      .query_row(
+        "DELETE FROM pastes WHERE id = ?1 AND expires_at <= ?2 RETURNING owned_bytes",
+        params![key, now],
+        |row| row.get(0),
+      )
+      .optional()?;
+
+    match size {
+      Some(size) => {
+        self.adjust_used_bytes(-size);
+        Ok(true)
+      }
+      None => Ok(false),
+    }
+  }
+
+  /// key and expiry (unix timestamp) of every record that expires at all
+  pub fn expiry_schedule(&self) -> rusqlite::Result<Vec<(String, i64)>> {
+    let conn = self.conn();
+    let mut stmt =
+      conn.prepare("SELECT id, expires_at FROM pastes WHERE expires_at IS NOT NULL")?;
+    let schedule = stmt
+      .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+      .collect();
+    schedule
+  }
+
+  /// Marks a burn-after-reading paste as read. Only the first call for a paste returns
+  /// `true`, that caller gets to serve the content and delete it.
+  ///
+  /// The record stays behind as a tombstone, see `entomb`, so later reads can tell a burned
+  /// paste from one that never existed.
+  pub fn claim(&self, key: &str) -> rusqlite::Result<bool> {
+    // the connection's lock makes the check and the update one step
+    let conn = self.conn();
+    let row: Option<(i64, Option<i64>)> = conn
+      .query_row(
+        "SELECT owned_bytes, expires_at FROM pastes WHERE id = ?1 AND consumed = 0",
+        [key],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+      )
+      .optional()?;
+
+    let (size, expires_at) = match row {
+      Some(row) => row,
+      None => return Ok(false),
+    };
+
+    self.entomb(&conn, key, "consumed = 1", size, expires_at)?;
+
+    Ok(true)
+  }
+
+  /// Counts a read of a paste with a view limit. Returns the number of views left after this
+  /// one, or `None` when there were none left to take.
+  ///
+  /// Like `claim`, the record of a paste that ran out of views stays behind as a tombstone,
+  /// whoever took the last view deletes the content.
+  pub fn take_view(&self, key: &str) -> rusqlite::Result<Option<u64>> {
+    let conn = self.conn();
+    let row: Option<(i64, i64, Option<i64>)> = conn
+      .query_row(
+        "SELECT remaining_views, owned_bytes, expires_at FROM pastes
+          WHERE id = ?1 AND remaining_views > 0",
+        [key],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+      )
+      .optional()?;
+
+    let (views, size, expires_at) = match row {
+      Some(row) => row,
+      None => return Ok(None),
+    };
+
+    if views == 1 {
+      self.entomb(&conn, key, "remaining_views = 0", size, expires_at)?;
+    } else {
+      conn.execute(
+        "UPDATE pastes SET remaining_views = remaining_views - 1 WHERE id = ?1",
+        [key],
+      )?;
+    }
+
+    Ok(Some((views - 1) as u64))
+  }
+
+  /// Lowers the views left of a paste to the given number, unless it has no more than that
+  /// already. On shared stores the views are taken through markers instead of `take_view`,
+  /// see `core::Record::take_view`, and only their outcome is written here.
+  pub fn lower_views(&self, key: &str, views_left: u64) -> rusqlite::Result<()> {
+    let conn = self.conn();
+    let row: Option<(i64, Option<i64>)> = conn
+      .query_row(
+        "SELECT owned_bytes, expires_at FROM pastes WHERE id = ?1 AND remaining_views > ?2",
+        params![key, views_left as i64],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+      )
+      .optional()?;
+
+    let (size, expires_at) = match row {
+      Some(row) => row,
+      None => return Ok(()),
+    };
+
+    if views_left == 0 {
+      return self.entomb(&conn, key, "remaining_views = 0", size, expires_at);
+    }
+    conn.execute(
+      "UPDATE pastes SET remaining_views = ?2 WHERE id = ?1",
+      params![key, views_left as i64],
+    )?;
+    Ok(())
+  }
+
+  /// Leaves the record of a paste whose content is about to go, after its one read or its
+  /// last view, as a tombstone: without a size, with `spent` (`consumed = 1` or
+  /// `remaining_views = 0`) set, until its expiry. Permanent pastes have none, theirs is kept
+  /// for `DEFAULT_MAX_RETENTION`.
+  fn entomb(
+    &self,
+    conn: &Connection,
+    key: &str,
+    spent: &str,
+    size: i64,
+    expires_at: Option<i64>,
+  ) -> rusqlite::Result<()> {
+    let buried_until =
+      expires_at.unwrap_or_else(|| Utc::now().timestamp() + DEFAULT_MAX_RETENTION as i64);
+    conn.execute(
+      &format!(
+        "UPDATE pastes SET {}, size = 0, owned_bytes = 0, expires_at = ?2 WHERE id = ?1",
+        spent
+      ),
+      params![key, buried_until],
+    )?;
+    self.adjust_used_bytes(-size);
+
+    if expires_at.is_none() {
+      self.notify_expiry(key, buried_until);
+    }
+    Ok(())
+  }
+
+  /// the records closest to expiring first, these are the first to go when space runs out.
+  /// Permanent records never are.
+  pub fn soonest_expiring(&self, limit: u32) -> rusqlite::Result<Vec<Record>> {
+    self.query_records(
+      &format!(
+        "SELECT {} FROM pastes WHERE expires_at IS NOT NULL ORDER BY expires_at, id LIMIT ?1",
+        RECORD_COLUMNS
+      ),
+      &limit,
+    )
+  }
+
+  /// every record that hasn't expired as of the given unix timestamp, permanent ones included
+  pub fn live_records(&self, now: i64) -> rusqlite::Result<Vec<Record>> {
+    self.query_records(
+      &format!(
+        "SELECT {} FROM pastes WHERE expires_at > ?1 OR expires_at IS NULL",
+        RECORD_COLUMNS
+      ),
+      &now,
+    )
+  }
+
+  /// Points the paste at the blob with the given digest and bumps the blob's reference count.
+  /// Returns the new reference count. `size` is counted in `used_bytes` when this creates
+  /// the blob.
+  pub fn link_blob(&self, key: &str, digest: &str, size: u64) -> rusqlite::Result<i64> {
+    let mut conn = self.conn();
+    let tx = conn.transaction()?;
+
+    // re-linking a paste must not leave a dangling reference on its previous blob
+    let freed = match MetadataIndex::unlink(&tx, key)? {
+      Some((previous, _)) => MetadataIndex::forget_unreferenced_blob(&tx, &previous)?,
+      None => 0,
+    };
+
+    tx.execute(
+      "INSERT INTO blobs (digest, refcount, size) VALUES (?1, 1, ?2)
+       ON CONFLICT (digest) DO UPDATE SET refcount = refcount + 1",
+      params![digest, size as i64],
+    )?;
+    tx.execute(
+      "INSERT INTO paste_blobs (id, digest) VALUES (?1, ?2)",
+      [key, digest],
+    )?;
+    let refcount = tx.query_row(
+      "SELECT refcount FROM blobs WHERE digest = ?1",
+      [digest],
+      |row| row.get(0),
+    )?;
+
+    tx.commit()?;
+    if refcount == 1 {
+      self.adjust_used_bytes(size as i64);
+    }
+    self.adjust_used_bytes(-freed);
+    Ok(refcount)
+  }
+
+  /// Deletes the blob's row once nothing references it anymore. Returns the bytes that freed.
+  fn forget_unreferenced_blob(conn: &Connection, digest: &str) -> rusqlite::Result<i64> {
+    Ok(
+      conn
+        .query_row(
+          "DELETE FROM blobs WHERE digest = ?1 AND refcount <= 0 RETURNING size",
+          [digest],
+          |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(0),
+    )
+  }
+
+  fn unlink(conn: &Connection, key: &str) -> rusqlite::Result<Option<(String, i64)>> {
+    let digest: Option<String> = conn
+      .query_row(
+        "SELECT digest FROM paste_blobs WHERE id = ?1",
+        [key],
+        |row| row.get(0),
+      )
+      .optional()?;
+
+    let digest = match digest {
+      Some(digest) => digest,
+      None => return Ok(None),
+    };
+
+    conn.execute("DELETE FROM paste_blobs WHERE id = ?1", [key])?;
+    conn.execute(
+      "UPDATE blobs SET refcount = refcount - 1 WHERE digest = ?1",
+      [&digest],
+    )?;
+    let refcount = conn.query_row(
+      "SELECT refcount FROM blobs WHERE digest = ?1",
+      [&digest],
+      |row| row.get(0),
+    )?;
+
+    Ok(Some((digest, refcount)))
+  }
+
+  /// Drops the paste's reference to its blob. Returns the blob's digest and how many
+  /// pastes still reference it, or `None` when the paste wasn't linked to a blob.
+  /// Blobs nobody references anymore are forgotten right away.
+  pub fn unlink_blob(&self, key: &str) -> rusqlite::Result<Option<(String, i64)>> {
+    let mut conn = self.conn();
+    let tx = conn.transaction()?;
+
+    let unlinked = MetadataIndex::unlink(&tx, key)?;
+    let freed = match &unlinked {
+      Some((digest, _)) => MetadataIndex::forget_unreferenced_blob(&tx, digest)?,
+      None => 0,
+    };
+
+    tx.commit()?;
+    self.adjust_used_bytes(-freed);
+    Ok(unlinked)
+  }
+
+  /// `true` while any paste links to the blob with the given digest
+  pub fn has_blob(&self, digest: &str) -> rusqlite::Result<bool> {
+    Ok(
+      self
+        .conn()
+        .query_row(
+          "SELECT 1 FROM blobs WHERE digest = ?1",
+          [digest],
+          |_| Ok(()),
+        )
+        .optional()?
+        .is_some(),
+    )
+  }
+
+  pub fn blob_for(&self, key: &str) -> rusqlite::Result<Option<String>> {
+    self
+      .conn()
+      .query_row(
+        "SELECT digest FROM paste_blobs WHERE id = ?1",
+        [key],
+        |row| row.get(0),
+      )
+      .optional()
+  }
+
+  /// IDs of every record, tombstones included
+  pub fn ids(&self) -> rusqlite::Result<Vec<String>> {
+    let conn = self.conn();
+    let mut stmt = conn.prepare("SELECT id FROM pastes")?;
+    let ids = stmt.query_map([], |row| row.get(0))?.collect();
+    ids
+  }
+
+  /// IDs of all the pastes that point at a blob
+  pub fn linked_keys(&self) -> rusqlite::Result<Vec<String>> {
+    let conn = self.conn();
+    let mut stmt = conn.prepare("SELECT id FROM paste_blobs")?;
+    let keys = stmt.query_map([], |row| row.get(0))?.collect();
+    keys
+  }
+
+  /// One-time import of the JSON-lines `deletions/<date>.txt` files the service used to keep.
+  /// Every imported file is removed afterwards, so running it again is a no-op.
+  pub fn import_deletions_dir(
+    &self,
+    directory_name: &str,
+    store: &dyn PasteStore,
+  ) -> Result<u32, Box<dyn Error>> {
+    if !Path::new(directory_name).is_dir() {
+      return Ok(0);
+    }
+
+    let mut counter: u32 = 0;
+    for entry in fs::read_dir(directory_name)? {
+      let path = entry?.path();
+      let dated = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .is_some_and(|stem| NaiveDate::parse_from_str(stem, SIMPLE_DATE_FORMAT).is_ok());
+      if !dated {
+        continue;
+      }
+
+      for line in fs::read_to_string(&path)?.lines() {
+        if line.is_empty() {
+          continue;
+        }
+
+        let mut record = match serde_json::from_str::<Record>(line) {
+          Ok(record) => record,
+          Err(e) => {
+            println!("skipping unreadable record in {:?}. Error: {}", path, e);
+            continue;
+          }
+        };
+
+        if let Some(content) = store.get(&record.key)? {
+          record.size = content.len() as u64;
+        }
+
+        // the file's date could be the local one of the host that wrote it, only the
+        // record's own expiry counts
+        self.insert(&record)?;
+        counter += 1;
+      }
+
+      fs::remove_file(&path)?;
+    }
+
+    Ok(counter)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::store::MemoryStore;
+
+  #[test]
+  fn test_insert_and_get() {
+    let index = MetadataIndex::open_in_memory().unwrap();
+    let mut record = Record::new("abcd".to_string(), 60);
+    record.size = 11;
+    index.insert(&record).unwrap();
+
+    let stored = index.get("abcd").unwrap().unwrap();
+    assert_eq!(60, stored.expiry);
+    assert_eq!(11, stored.size);
+    assert!(index.contains("abcd").unwrap());
+    assert_eq!(1, index.live_records(Utc::now().timestamp()).unwrap().len());
+
+    assert!(index.remove("abcd").unwrap());
+    assert!(index.get("abcd").unwrap().is_none());
+  }
+
+  #[test]
+  fn test_failed_migration_leaves_nothing_behind() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    let migrations = [
+      "CREATE TABLE first (id TEXT);",
+      "CREATE TABLE second (id TEXT); INSERT INTO missing VALUES (1);",
+    ];
+    assert!(MetadataIndex::migrate(&mut conn, &migrations).is_err());
+
+    let version: usize = conn
+      .query_row("PRAGMA user_version", [], |row| row.get(0))
+      .unwrap();
+    assert_eq!(1, version);
+    let tables: i64 = conn
+      .query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE name = 'second'",
+        [],
+        |row| row.get(0),
+      )
+      .unwrap();
+    assert_eq!(0, tables);
+  }
+
+  #[test]
+  fn test_reserve_id() {
+    let index = MetadataIndex::open_in_memory().unwrap();
+    let now = Utc::now().timestamp();
+
+    assert!(index.reserve_id("abcd", now).unwrap());
+    assert!(!index.reserve_id("abcd", now).unwrap());
+
+    index.insert(&Record::new("abcd".to_string(), 60)).unwrap();
+    assert!(!index.reserve_id("abcd", now).unwrap());
+
+    // an upload that never got logged lets go of its ID eventually
+    assert!(index.reserve_id("efgh", now).unwrap());
+    assert!(index.reserve_id("efgh", now + RESERVATION_TTL).unwrap());
+  }
+
+  #[test]
+  fn test_claim_only_once() {
+    let index = MetadataIndex::open_in_memory().unwrap();
+    let mut record = Record::new("abcd".to_string(), 60);
+    record.size = 11;
+    record.burn_after_reading = true;
+    index.insert(&record).unwrap();
+
+    assert!(!index.is_tombstone("abcd").unwrap());
+    assert!(index.claim("abcd").unwrap());
+    assert!(index.is_tombstone("abcd").unwrap());
+    assert!(!index.claim("abcd").unwrap());
+    assert!(!index.claim("efgh").unwrap());
+    assert!(index.get("abcd").unwrap().unwrap().burn_after_reading);
+    assert_eq!(0, index.used_bytes());
+  }
+
+  #[test]
+  fn test_take_view_counts_down() {
+    let index = MetadataIndex::open_in_memory().unwrap();
+    let mut record = Record::new("abcd".to_string(), 60);
+    record.size = 11;
+    record.remaining_views = Some(2);
+    index.insert(&record).unwrap();
+
+    assert_eq!(Some(1), index.take_view("abcd").unwrap());
+    assert_eq!(Some(1), index.get("abcd").unwrap().unwrap().remaining_views);
+    assert_eq!(Some(0), index.take_view("abcd").unwrap());
+    assert_eq!(None, index.take_view("abcd").unwrap());
+    assert_eq!(0, index.used_bytes());
+
+    index.insert(&Record::new("efgh".to_string(), 60)).unwrap();
+    assert_eq!(None, index.take_view("efgh").unwrap());
+  }
+
+  #[test]
+  fn test_permanent_pastes_leave_a_tombstone_that_expires() {
+    let index = MetadataIndex::open_in_memory().unwrap();
+    let mut record = Record::permanent("abcd".to_string());
+    record.remaining_views = Some(1);
+    index.insert(&record).unwrap();
+
+    assert_eq!(Some(0), index.take_view("abcd").unwrap());
+    assert_eq!(None, index.take_view("abcd").unwrap());
+    assert!(!index.get("abcd").unwrap().unwrap().permanent);
+
+    record.key = "efgh".to_string();
+    record.remaining_views = None;
+    record.burn_after_reading = true;
+    index.insert(&record).unwrap();
+
+    assert!(index.claim("efgh").unwrap());
+    assert!(!index.claim("efgh").unwrap());
+
+    let now = Utc::now().timestamp();
+    let schedule = index.expiry_schedule().unwrap();
+    assert_eq!(2, schedule.len());
+    assert!(schedule
+      .iter()
+      .all(|(_, expires_at)| *expires_at >= now + DEFAULT_MAX_RETENTION as i64 - 1));
+  }
+
+  #[test]
+  fn test_used_bytes() {
+    let index = MetadataIndex::open_in_memory().unwrap();
+    let mut record = Record::new("aaaa".to_string(), 60);
+    record.size = 100;
+    index.insert(&record).unwrap();
+    record.size = 40;
+    index.insert(&record).unwrap();
+    assert_eq!(40, index.used_bytes());
+
+    let mut other = Record::new("bbbb".to_string(), 30);
+    other.size = 10;
+    index.insert(&other).unwrap();
+    assert_eq!(50, index.used_bytes());
+    assert_eq!("bbbb", index.soonest_expiring(1).unwrap()[0].key);
+
+    index.remove("bbbb").unwrap();
+    assert_eq!(40, index.used_bytes());
+    index.remove("aaaa").unwrap();
+    assert_eq!(0, index.used_bytes());
+  }
+
+  #[test]
+  fn test_used_bytes_count_shared_blobs_once() {
+    let index = MetadataIndex::open_in_memory().unwrap();
+    for key in ["aaaa", "bbbb"] {
+      index.link_blob(key, "digest", 100).unwrap();
+      let mut record = Record::new(key.to_string(), 60);
+      record.size = 100;
+      index.insert(&record).unwrap();
+    }
+    assert_eq!(100, index.used_bytes());
+
+    index.remove("aaaa").unwrap();
+    index.unlink_blob("aaaa").unwrap();
+    assert_eq!(100, index.used_bytes());
+
+    index.unlink_blob("bbbb").unwrap();
+    index.remove("bbbb").unwrap();
+    assert_eq!(0, index.used_bytes());
+  }
+
+  #[test]
+  fn test_import_deletions_dir() {
+    let dir = std::env::temp_dir().join(format!("pastebin-deletions-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let record = Record::new("u7F1".to_string(), 15);
+    let legacy = r#"{"expiry":15,"key":"abcd","created_time":"Sun, 11 Jul 2021 01:30:00 +0530"}"#;
+    fs::write(
+      dir.join("2021-07-12.txt"),
+      format!("{}\n{}\n", serde_json::to_string(&record).unwrap(), legacy),
+    )
+    .unwrap();
+
+    let store = MemoryStore::new();
+    store.put("u7F1", b"hello").unwrap();
+
+    let index = MetadataIndex::open_in_memory().unwrap();
+    assert_eq!(
+      2,
+      index
+        .import_deletions_dir(dir.to_str().unwrap(), &store)
+        .unwrap()
+    );
+    assert_eq!(5, index.get("u7F1").unwrap().unwrap().size);
+    assert_eq!(
+      Utc.ymd(2021, 7, 10).and_hms(20, 0, 0),
+      index.get("abcd").unwrap().unwrap().created_time
+    );
+    assert_eq!(1, index.live_records(Utc::now().timestamp()).unwrap().len());
+    assert_eq!(
+      0,
+      index
+        .import_deletions_dir(dir.to_str().unwrap(), &store)
+        .unwrap()
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
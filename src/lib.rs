@@ -1,20 +1,196 @@
+pub mod codec;
 pub mod core;
-pub mod fairings;
+pub mod crypto;
+pub mod dedup;
+pub mod envelope;
+pub mod expiry;
+pub mod ids;
+pub mod index;
 pub mod macros;
 pub mod param_guards;
+pub mod quota;
 pub mod request_guards;
+pub mod responders;
+pub mod routes;
+pub mod s3;
+pub mod store;
 pub mod util;
 
+use crypto::{EncryptedStore, Keyring};
+use dedup::DedupStore;
+use ids::{IdAlphabet, IdConfig};
+use index::SharedMetadataIndex;
+use quota::{QuotaPolicy, StorageQuota};
+use rocket::data::ByteUnit;
+use s3::S3Config;
+use std::io;
+use std::sync::Arc;
+use store::{SharedPasteStore, StorageBackend};
+
 pub struct CustomConfig {
   pub exposable_url: String,
+  pub storage: StorageBackend,
+  /// path of the SQLite metadata index, `:memory:` keeps it in memory
+  pub index_path: String,
+  /// store identical paste bodies only once
+  pub deduplicate: bool,
+  /// gzip the pastes at rest
+  pub compress: bool,
+  /// encrypt the pastes at rest, `None` when no keys are configured
+  pub keyring: Option<Arc<Keyring>>,
+  /// byte budget of the stored pastes, `None` when unlimited
+  pub quota: Option<Arc<StorageQuota>>,
+  /// longest a paste can be kept, in seconds
+  pub max_retention: u64,
+  /// keys accepted by the routes guarded with `request_guards::ApiKey`
+  pub api_keys: Vec<String>,
+  /// how random paste IDs look, see `ids::UniqueID`
+  pub ids: IdConfig,
+}
+
+fn env_or(key: &str, default: &str) -> String {
+  std::env::var(key).unwrap_or_else(|_| String::from(default))
+}
+
+/// the parsed value of the variable, `default` when it isn't set. Panics naming the variable
+/// and the value it couldn't parse, like the rest of the configuration
+fn env_parsed<T: std::str::FromStr>(key: &str, default: T) -> T
+where
+  T::Err: std::fmt::Display,
+{
+  match std::env::var(key) {
+    Ok(value) => match value.trim().parse::<T>() {
+      Ok(parsed) => parsed,
+      Err(e) => panic!("invalid {} configured ({}). Error: {}", key, value, e),
+    },
+    Err(_) => default,
+  }
 }
 
 impl CustomConfig {
   pub fn new() -> Self {
-    let exposable_url = std::env::var("PASTEBIN_EXPOSABLE_URL")
-      .or::<String>(Ok(String::from("http://localhost:8000")))
-      .unwrap();
+    let exposable_url = env_or("PASTEBIN_EXPOSABLE_URL", "http://localhost:8000");
+
+    // PASTEBIN_STORAGE => `filesystem` (default) | `memory` | `s3`
+    let storage = match env_or("PASTEBIN_STORAGE", "filesystem").as_str() {
+      "memory" => StorageBackend::Memory,
+      "s3" => StorageBackend::S3(S3Config {
+        endpoint: env_or("PASTEBIN_S3_ENDPOINT", "https://s3.amazonaws.com"),
+        bucket: env_or("PASTEBIN_S3_BUCKET", "pastebin"),
+        prefix: env_or("PASTEBIN_S3_PREFIX", ""),
+        region: env_or("PASTEBIN_S3_REGION", "us-east-1"),
+        access_key: env_or("PASTEBIN_S3_ACCESS_KEY", ""),
+        secret_key: env_or("PASTEBIN_S3_SECRET_KEY", ""),
+      }),
+      _ => StorageBackend::FileSystem(env_or("PASTEBIN_UPLOAD_DIR", "upload").into()),
+    };
+
+    let index_path = env_or("PASTEBIN_INDEX_PATH", "index/pastes.db");
+
+    let deduplicate = env_or("PASTEBIN_DEDUPLICATE", "true") == "true";
+
+    let compress = env_or("PASTEBIN_COMPRESS", "true") == "true";
+
+    // PASTEBIN_ENCRYPTION_KEYS => `<key id>:<64 hex chars>,...`, the active key defaults to
+    // the last one listed
+    let keyring = std::env::var("PASTEBIN_ENCRYPTION_KEYS").ok().map(|keys| {
+      let active = std::env::var("PASTEBIN_ENCRYPTION_KEY_ID").ok();
+      match Keyring::parse(&keys, active.as_deref()) {
+        Ok(keyring) => Arc::new(keyring),
+        Err(e) => panic!("invalid encryption keys configured. Error: {}", e),
+      }
+    });
+
+    // PASTEBIN_STORAGE_QUOTA => `512MiB` | `1GB` | bytes, PASTEBIN_QUOTA_POLICY => `reject`
+    // (default) | `evict`
+    let quota = std::env::var("PASTEBIN_STORAGE_QUOTA").ok().map(|budget| {
+      let budget = match budget.parse::<ByteUnit>() {
+        Ok(budget) => budget.as_u64(),
+        Err(e) => panic!("invalid storage quota configured. Error: {}", e),
+      };
+      match QuotaPolicy::parse(&env_or("PASTEBIN_QUOTA_POLICY", "reject")) {
+        Ok(policy) => Arc::new(StorageQuota::new(budget, policy)),
+        Err(e) => panic!("invalid quota policy configured. Error: {}", e),
+      }
+    });
+
+    // PASTEBIN_MAX_RETENTION_DAYS => 30 (default)
+    let max_retention = match std::env::var("PASTEBIN_MAX_RETENTION_DAYS") {
+      Ok(days) => match days.parse::<u64>() {
+        Ok(days) => days * 86_400,
+        Err(e) => panic!("invalid maximum retention configured. Error: {}", e),
+      },
+      Err(_) => core::DEFAULT_MAX_RETENTION,
+    };
+
+    // PASTEBIN_API_KEYS => `<key>,<key>,...`, none by default
+    let api_keys = env_or("PASTEBIN_API_KEYS", "")
+      .split(',')
+      .map(|key| key.trim().to_string())
+      .filter(|key| !key.is_empty())
+      .collect();
+
+    // PASTEBIN_ID_ALPHABET => `base62` (default) | `crockford32` | `words`, PASTEBIN_ID_LENGTH
+    // => 4 (default), 3 words for `words`, PASTEBIN_ID_GROW_AT => 0.5 (default),
+    // PASTEBIN_BLOOM_EXPECTED_ITEMS and PASTEBIN_BLOOM_FALSE_POSITIVE_RATE size the Bloom filters
+    let defaults = IdConfig::default();
+    let alphabet = match IdAlphabet::parse(&env_or("PASTEBIN_ID_ALPHABET", "base62")) {
+      Ok(alphabet) => alphabet,
+      Err(e) => panic!("invalid ID alphabet configured. Error: {}", e),
+    };
+    let ids = IdConfig {
+      length: env_parsed("PASTEBIN_ID_LENGTH", alphabet.default_length()),
+      alphabet,
+      expected_items: env_parsed("PASTEBIN_BLOOM_EXPECTED_ITEMS", defaults.expected_items),
+      false_positive_rate: env_parsed(
+        "PASTEBIN_BLOOM_FALSE_POSITIVE_RATE",
+        defaults.false_positive_rate,
+      ),
+      grow_at: env_parsed("PASTEBIN_ID_GROW_AT", defaults.grow_at),
+    };
+    if let Err(e) = ids.validate() {
+      panic!("invalid ID configuration. Error: {}", e);
+    }
+
+    CustomConfig {
+      exposable_url,
+      storage,
+      index_path,
+      deduplicate,
+      compress,
+      keyring,
+      quota,
+      max_retention,
+      api_keys,
+      ids,
+    }
+  }
+
+  /// Opens the configured storage backend with the encryption and deduplication layers on
+  /// top, as enabled.
+  pub fn build_store(&self, index: SharedMetadataIndex) -> io::Result<SharedPasteStore> {
+    let mut store = self.storage.open()?;
+
+    if let Some(keyring) = &self.keyring {
+      store = Arc::new(EncryptedStore::new(store, keyring.clone()));
+    }
+
+    // the blob reference counts live in this instance's index, other instances sharing the
+    // storage would delete blobs still referenced here
+    if self.deduplicate && !store.is_shared() {
+      let naming_key = self
+        .keyring
+        .as_ref()
+        .map(|keyring| keyring.blob_naming_key());
+      store = Arc::new(DedupStore::new(store, index, naming_key));
+    }
+
+    Ok(store)
+  }
+}
 
-    CustomConfig { exposable_url }
+impl Default for CustomConfig {
+  fn default() -> Self {
+    CustomConfig::new()
   }
 }
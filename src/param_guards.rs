@@ -1,127 +1,586 @@
-use crate::core::Record;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use rocket::http::Status;
 use rocket::request::FromParam;
+use rocket::FromForm;
+use std::fmt;
 
 pub struct ID(pub String);
 
 impl<'r> FromParam<'r> for ID {
   type Error = &'r str;
 
+  /// generated IDs are alphanumeric, custom ones can have hyphens too, see `Slug`
   fn from_param(param: &'r str) -> Result<Self, Self::Error> {
-    match param.chars().all(|c| c.is_ascii_alphanumeric()) {
+    let valid = !param.is_empty()
+      && param.len() <= Slug::MAX_LENGTH
+      && param.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+
+    match valid {
       true => Ok(ID(param.into())),
       false => Err(param),
     }
   }
 }
 
-#[derive(Debug)]
-pub struct TimeParam {
-  pub duration: std::time::Duration,
-  pub is_more_than_a_day: bool,
-  pub error: String,
-  pub deletion_date: String,
+/// A custom ID asked for with `?slug=<slug>` on upload, like `deploy-checklist`.
+#[derive(Debug, PartialEq)]
+pub struct Slug(pub String);
+
+impl Slug {
+  pub const MIN_LENGTH: usize = 3;
+  pub const MAX_LENGTH: usize = 48;
+
+  /// taken by the routes, or likely to be some day
+  const RESERVED: [&'static str; 9] = [
+    "api", "burn", "extend", "index", "private", "static", "stats", "upload", "view",
+  ];
+
+  /// letters, digits and single hyphens between them
+  pub fn parse(value: &str) -> Result<Slug, String> {
+    if value.len() < Slug::MIN_LENGTH || value.len() > Slug::MAX_LENGTH {
+      return Err(format!(
+        "`slug` has to be {} to {} characters long. Input: ({})",
+        Slug::MIN_LENGTH,
+        Slug::MAX_LENGTH,
+        value
+      ));
+    }
+
+    let well_formed = value
+      .split('-')
+      .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()));
+    if !well_formed {
+      return Err(format!(
+        "`slug` can only have letters, digits and single hyphens between them. Input: ({})",
+        value
+      ));
+    }
+
+    if Slug::RESERVED
+      .iter()
+      .any(|reserved| reserved.eq_ignore_ascii_case(value))
+    {
+      return Err(format!("`slug` ({}) is reserved", value));
+    }
+
+    Ok(Slug(value.to_string()))
+  }
 }
 
-impl<'r> FromParam<'r> for TimeParam {
-  type Error = String;
+/// Query of the upload routes, every field is optional.
+#[derive(Debug, FromForm)]
+pub struct UploadQuery<'r> {
+  /// the paste expires after that many reads, see `parse_max_views`
+  pub max_views: Option<&'r str>,
+  /// absolute expiry instead of a relative one, see `parse_expires_at`
+  pub expires_at: Option<&'r str>,
+  /// sliding expiry, every read keeps the paste around for another `idle`, see
+  /// `parse_duration`
+  pub idle: Option<&'r str>,
+  /// `?permanent`, the paste never expires, see `parse_flag`
+  pub permanent: Option<&'r str>,
+  /// `?burn`, the paste is deleted once it's read, see `parse_flag`
+  pub burn: Option<&'r str>,
+}
 
-  fn from_param(param: &'r str) -> Result<Self, Self::Error> {
-    let params = param
-      .split(&['d', 'm', 'h', 's'][..])
-      .collect::<Vec<&str>>();
+/// Flags like `?permanent` of the upload routes => `` (given without a value) | `true` |
+/// `false` | `1` | `0`
+pub fn parse_flag(name: &str, value: &str) -> Result<bool, String> {
+  match value {
+    "" | "true" | "1" => Ok(true),
+    "false" | "0" => Ok(false),
+    _ => Err(format!(
+      "`{}` is a flag, give it without a value or as true/false. Input: ({})",
+      name, value
+    )),
+  }
+}
+
+/// `?max_views=<n>` of the upload routes
+pub fn parse_max_views(value: &str) -> Result<u64, String> {
+  match value.parse::<u64>() {
+    Ok(views) if views > 0 => Ok(views),
+    _ => Err(format!(
+      "`max_views` has to be a whole number greater than 0. Input: ({})",
+      value
+    )),
+  }
+}
 
-    let mut u32_params = vec![];
+/// `?expires_at=<timestamp>` of the upload routes => `2026-11-01T00:00:00Z` (RFC 3339) |
+/// `2026-11-01` (the start of that day, in UTC). Returns the number of seconds until then,
+/// which has to be in the future and at most `max_retention` seconds away.
+pub fn parse_expires_at(value: &str, max_retention: u64) -> Result<u64, String> {
+  let value = restore_offset_sign(value.trim());
 
-    for param in params {
-      if param.is_empty() {
-        continue;
+  let expires_at = match DateTime::parse_from_rfc3339(&value) {
+    Ok(expires_at) => expires_at.with_timezone(&Utc),
+    Err(_) => match NaiveDate::parse_from_str(&value, "%Y-%m-%d") {
+      Ok(date) => Utc.from_utc_datetime(&date.and_hms(0, 0, 0)),
+      Err(_) => {
+        return Err(format!(
+          "`expires_at` has to be an RFC 3339 timestamp or a date (YYYY-MM-DD). Input: ({})",
+          value
+        ))
       }
+    },
+  };
 
-      let parsed_data = param.parse::<u32>();
+  let seconds = (expires_at - Utc::now()).num_seconds();
+  if seconds <= 0 {
+    return Err(format!("`expires_at` ({}) is in the past", value));
+  }
 
-      if parsed_data.is_err() {
-        continue;
-      }
+  if seconds as u64 > max_retention {
+    return Err(format!(
+      "`expires_at` ({}) is further away than the maximum retention of {} days",
+      value,
+      max_retention / 86_400
+    ));
+  }
+
+  Ok(seconds as u64)
+}
+
+/// Query values are form-decoded, so an unencoded `+` of a timestamp's offset
+/// (`2026-11-01T00:00:00+02:00`) arrives as a space. Only a space right before a trailing
+/// `HH:MM` can be one, every other space is left as it is.
+fn restore_offset_sign(value: &str) -> String {
+  let offset_start = match value.len().checked_sub(6) {
+    Some(offset_start) if value.is_char_boundary(offset_start) => offset_start,
+    _ => return value.to_string(),
+  };
+
+  let (timestamp, offset) = value.split_at(offset_start);
+  let offset = offset.as_bytes();
+  let is_offset = offset[0] == b' '
+    && offset[1..3].iter().all(u8::is_ascii_digit)
+    && offset[3] == b':'
+    && offset[4..].iter().all(u8::is_ascii_digit);
 
-      u32_params.push(parsed_data.unwrap());
+  match is_offset && timestamp.contains('T') {
+    true => format!("{}+{}", timestamp, &value[offset_start + 1..]),
+    false => value.to_string(),
+  }
+}
+
+/// Why a duration couldn't be parsed, positions are 0-based character offsets into the input.
+#[derive(Debug, PartialEq)]
+pub enum DurationError {
+  Empty,
+  ExpectedNumber {
+    input: String,
+    position: usize,
+  },
+  MissingUnit {
+    input: String,
+    position: usize,
+  },
+  UnknownUnit {
+    input: String,
+    position: usize,
+    unit: String,
+  },
+  RepeatedUnit {
+    input: String,
+    position: usize,
+    unit: String,
+  },
+  Overflow {
+    input: String,
+  },
+  Zero {
+    input: String,
+  },
+  /// longer than the configured maximum, in seconds
+  TooLong {
+    input: String,
+    max: u64,
+  },
+}
+
+impl DurationError {
+  /// 400 for input that isn't a duration at all, 422 for a duration that isn't allowed
+  pub fn status(&self) -> Status {
+    match self {
+      DurationError::Zero { .. } | DurationError::TooLong { .. } => Status::UnprocessableEntity,
+      _ => Status::BadRequest,
     }
+  }
+}
 
-    if u32_params.len() == 0 {
-      return Err(format!(
-        "This route call might not be intentional! Input: ({})",
-        param
-      ));
+impl fmt::Display for DurationError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DurationError::Empty => write!(f, "the duration is empty"),
+      DurationError::ExpectedNumber { input, position } => write!(
+        f,
+        "expected a number at position {} of ({}), found `{}`",
+        position,
+        input,
+        input.chars().nth(*position).unwrap_or_default()
+      ),
+      DurationError::MissingUnit { input, position } => write!(
+        f,
+        "expected a unit (w, d, h, m, s, mo or y) at position {} of ({})",
+        position, input
+      ),
+      DurationError::UnknownUnit {
+        input,
+        position,
+        unit,
+      } => write!(
+        f,
+        "unknown unit `{}` at position {} of ({})",
+        unit, position, input
+      ),
+      DurationError::RepeatedUnit {
+        input,
+        position,
+        unit,
+      } => write!(
+        f,
+        "unit `{}` is given twice, again at position {} of ({})",
+        unit, position, input
+      ),
+      DurationError::Overflow { input } => write!(f, "the duration ({}) is too long", input),
+      DurationError::Zero { input } => write!(f, "the duration ({}) adds up to zero", input),
+      DurationError::TooLong { input, max } => write!(
+        f,
+        "the duration ({}) is longer than the maximum of {} seconds",
+        input, max
+      ),
     }
+  }
+}
+
+impl std::error::Error for DurationError {}
 
-    let params = u32_params;
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+/// months are counted as 30 days and years as 365, whatever the calendar says
+const MONTH: u64 = 30 * DAY;
+const YEAR: u64 = 365 * DAY;
 
-    let mut error_message = String::new();
+/// Adds up `<number><unit>` components, looking the units up with `unit_seconds`. `offset` is
+/// the position of `components` in `input`, for the error messages.
+fn sum_components(
+  input: &str,
+  components: &str,
+  offset: usize,
+  unit_seconds: impl Fn(&str) -> Option<u64>,
+) -> Result<u64, DurationError> {
+  let chars: Vec<char> = components.chars().collect();
+  let mut seen: Vec<String> = Vec::new();
+  let mut total: u64 = 0;
+  let mut i = 0;
 
-    #[derive(Default, Debug)]
-    struct Holder {
-      day: u32,
-      minute: u32,
-      hour: u32,
-      second: u32,
+  while i < chars.len() {
+    let number_start = i;
+    while i < chars.len() && chars[i].is_ascii_digit() {
+      i += 1;
+    }
+    if i == number_start {
+      return Err(DurationError::ExpectedNumber {
+        input: input.to_string(),
+        position: offset + i,
+      });
     }
+    let number: String = chars[number_start..i].iter().collect();
+    let value = number.parse::<u64>().map_err(|_| DurationError::Overflow {
+      input: input.to_string(),
+    })?;
 
-    let mut h = Holder {
-      ..Default::default()
-    };
-
-    let mut n = 1;
-
-    for i in ["s", "m", "h", "d"] {
-      if param.contains(i) {
-        let val = params[params.len() - n];
-        if i == "s" {
-          if val > 59 {
-            error_message = format!(
-              "error parsing `second` param ({}). `second` has to be less than 60.",
-              param
-            );
-            break;
-          }
-          h.second = val;
-        } else if i == "m" {
-          if val > 59 {
-            error_message = format!(
-              "error parsing `minute` param ({}). `minute` has to be less than 60.",
-              param
-            );
-            break;
-          }
-          h.minute = val;
-        } else if i == "h" {
-          if val > 23 {
-            error_message = format!(
-              "error parsing `hour` param ({}). `hour` has to be less than 24.",
-              param
-            );
-            break;
-          }
-          h.hour = val;
-        } else {
-          if val > 30 {
-            error_message = format!(
-              "error parsing `day` param ({}). `day` has to be less than 31.",
-              param
-            );
-            break;
-          }
-          h.day = val;
-        }
-        n += 1;
+    let unit_start = i;
+    while i < chars.len() && chars[i].is_ascii_alphabetic() {
+      i += 1;
+    }
+    if i == unit_start {
+      return Err(DurationError::MissingUnit {
+        input: input.to_string(),
+        position: offset + i,
+      });
+    }
+    let unit: String = chars[unit_start..i].iter().collect();
+
+    let seconds = unit_seconds(&unit).ok_or_else(|| DurationError::UnknownUnit {
+      input: input.to_string(),
+      position: offset + unit_start,
+      unit: unit.clone(),
+    })?;
+    if seen.contains(&unit) {
+      return Err(DurationError::RepeatedUnit {
+        input: input.to_string(),
+        position: offset + unit_start,
+        unit,
+      });
+    }
+    seen.push(unit);
+
+    total = value
+      .checked_mul(seconds)
+      .and_then(|seconds| total.checked_add(seconds))
+      .ok_or_else(|| DurationError::Overflow {
+        input: input.to_string(),
+      })?;
+  }
+
+  Ok(total)
+}
+
+/// `P1DT12H` => ISO 8601 duration, with `Y`, `M`, `W` and `D` before the `T` and `H`, `M`
+/// and `S` after it. Returns the number of seconds.
+fn parse_iso_8601(input: &str) -> Result<u64, DurationError> {
+  let body = &input[1..];
+  let (date, time) = match body.find(['T', 't']) {
+    Some(t) => (&body[..t], Some((&body[t + 1..], t + 2))),
+    None => (body, None),
+  };
+
+  if date.is_empty() && time.is_none() {
+    return Err(DurationError::MissingUnit {
+      input: input.to_string(),
+      position: input.len(),
+    });
+  }
+
+  let mut total = sum_components(input, date, 1, |unit| {
+    match unit.to_ascii_uppercase().as_str() {
+      "Y" => Some(YEAR),
+      "M" => Some(MONTH),
+      "W" => Some(WEEK),
+      "D" => Some(DAY),
+      _ => None,
+    }
+  })?;
+
+  if let Some((time, offset)) = time {
+    if time.is_empty() {
+      return Err(DurationError::ExpectedNumber {
+        input: input.to_string(),
+        position: offset,
+      });
+    }
+
+    let seconds = sum_components(input, time, offset, |unit| {
+      match unit.to_ascii_uppercase().as_str() {
+        "H" => Some(HOUR),
+        "M" => Some(MINUTE),
+        "S" => Some(1),
+        _ => None,
       }
+    })?;
+    total = total
+      .checked_add(seconds)
+      .ok_or_else(|| DurationError::Overflow {
+        input: input.to_string(),
+      })?;
+  }
+
+  Ok(total)
+}
+
+/// Parses a duration => `1w2d` | `36h` | `90m` | `2mo` | `1y` | `P1DT12H` (ISO 8601).
+/// Components don't need to be normalized nor ordered, but each unit can only appear once.
+pub fn parse_duration(input: &str) -> Result<u64, DurationError> {
+  if input.is_empty() {
+    return Err(DurationError::Empty);
+  }
+
+  let seconds = if input.starts_with(['P', 'p']) {
+    parse_iso_8601(input)?
+  } else {
+    sum_components(input, input, 0, |unit| match unit {
+      "y" => Some(YEAR),
+      "mo" => Some(MONTH),
+      "w" => Some(WEEK),
+      "d" => Some(DAY),
+      "h" => Some(HOUR),
+      "m" => Some(MINUTE),
+      "s" => Some(1),
+      _ => None,
+    })?
+  };
+
+  if seconds == 0 {
+    return Err(DurationError::Zero {
+      input: input.to_string(),
+    });
+  }
+
+  Ok(seconds)
+}
+
+/// A relative expiry in a route's path, see `parse_duration` for the accepted formats.
+///
+/// Take it as `Result<TimeParam, DurationError>` to answer bad input with the error's
+/// message instead of Rocket forwarding the request.
+#[derive(Debug)]
+pub struct TimeParam {
+  /// as given in the path
+  pub input: String,
+  pub duration: std::time::Duration,
+  pub is_more_than_a_day: bool,
+}
+
+impl TimeParam {
+  /// max => in seconds, `DurationError::TooLong` for anything longer
+  pub fn at_most(self, max: u64) -> Result<Self, DurationError> {
+    if self.duration.as_secs() > max {
+      return Err(DurationError::TooLong {
+        input: self.input,
+        max,
+      });
     }
 
-    let literal = (h.day * 24 * 60 * 60) + (h.hour * 60 * 60) + (h.minute * 60) + h.second;
+    Ok(self)
+  }
+}
+
+impl<'r> FromParam<'r> for TimeParam {
+  type Error = DurationError;
+
+  fn from_param(param: &'r str) -> Result<Self, Self::Error> {
+    let seconds = parse_duration(param)?;
 
     Ok(TimeParam {
-      duration: std::time::Duration::from_secs(literal as u64),
-      is_more_than_a_day: literal >= 86_400,
-      error: error_message,
-      deletion_date: Record::get_deletions_date_for_number_of_days(literal as i64),
+      input: param.to_string(),
+      duration: std::time::Duration::from_secs(seconds),
+      is_more_than_a_day: seconds >= DAY,
     })
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::Duration;
+
+  #[test]
+  fn test_parse_expires_at() {
+    let in_two_days = (Utc::now() + Duration::days(2)).to_rfc3339();
+    let seconds = parse_expires_at(&in_two_days, 3 * 86_400).unwrap();
+    assert!((172_790..=172_800).contains(&seconds));
+
+    let tomorrow = (Utc::now() + Duration::days(1))
+      .format("%Y-%m-%d")
+      .to_string();
+    assert!(parse_expires_at(&tomorrow, 3 * 86_400).unwrap() <= 86_400);
+
+    assert!(parse_expires_at(&in_two_days, 86_400).is_err());
+    assert!(parse_expires_at("2021-07-12T00:00:00Z", 86_400).is_err());
+    assert!(parse_expires_at("next friday", 86_400).is_err());
+  }
+
+  #[test]
+  fn test_restore_offset_sign() {
+    assert_eq!(
+      "2026-11-01T00:00:00+02:00",
+      restore_offset_sign("2026-11-01T00:00:00 02:00")
+    );
+    assert_eq!(
+      "2026-11-01T00:00:00-02:00",
+      restore_offset_sign("2026-11-01T00:00:00-02:00")
+    );
+    assert_eq!("next friday", restore_offset_sign("next friday"));
+    assert_eq!("2026 11 01", restore_offset_sign("2026 11 01"));
+    assert_eq!("a 12:00", restore_offset_sign("a 12:00"));
+  }
+
+  #[test]
+  fn test_slug() {
+    assert_eq!(
+      Ok(Slug("deploy-checklist".to_string())),
+      Slug::parse("deploy-checklist")
+    );
+    assert!(Slug::parse("ab").is_err());
+    assert!(Slug::parse(&"a".repeat(49)).is_err());
+    assert!(Slug::parse("-deploy").is_err());
+    assert!(Slug::parse("deploy--checklist").is_err());
+    assert!(Slug::parse("deploy_checklist").is_err());
+    assert!(Slug::parse("Stats").is_err());
+
+    assert!(ID::from_param("deploy-checklist").is_ok());
+    assert!(ID::from_param("brave-otter-mango").is_ok());
+    assert!(ID::from_param("../etc").is_err());
+  }
+
+  #[test]
+  fn test_parse_flag() {
+    assert_eq!(Ok(true), parse_flag("permanent", ""));
+    assert_eq!(Ok(false), parse_flag("permanent", "0"));
+    assert!(parse_flag("permanent", "forever").is_err());
+  }
+
+  #[test]
+  fn test_parse_duration() {
+    assert_eq!(Ok(25 * HOUR), parse_duration("25h"));
+    assert_eq!(Ok(90 * MINUTE), parse_duration("90m"));
+    assert_eq!(Ok(45 * DAY), parse_duration("45d"));
+    assert_eq!(Ok(WEEK + 2 * DAY + 30), parse_duration("1w2d30s"));
+    assert_eq!(Ok(2 * MONTH + 5 * MINUTE), parse_duration("5m2mo"));
+    assert_eq!(Ok(DAY + 12 * HOUR), parse_duration("P1DT12H"));
+    assert_eq!(Ok(MONTH + 30 * MINUTE), parse_duration("P1MT30M"));
+    assert_eq!(Ok(2 * WEEK), parse_duration("P2W"));
+  }
+
+  #[test]
+  fn test_parse_duration_errors() {
+    assert_eq!(Err(DurationError::Empty), parse_duration(""));
+    assert_eq!(
+      Err(DurationError::UnknownUnit {
+        input: "3x5h".to_string(),
+        position: 1,
+        unit: "x".to_string()
+      }),
+      parse_duration("3x5h")
+    );
+    assert_eq!(
+      Err(DurationError::MissingUnit {
+        input: "1h30".to_string(),
+        position: 4
+      }),
+      parse_duration("1h30")
+    );
+    assert_eq!(
+      Err(DurationError::ExpectedNumber {
+        input: "h1".to_string(),
+        position: 0
+      }),
+      parse_duration("h1")
+    );
+    assert!(matches!(
+      parse_duration("1h2h"),
+      Err(DurationError::RepeatedUnit { position: 3, .. })
+    ));
+    assert!(matches!(
+      parse_duration("P1DT"),
+      Err(DurationError::ExpectedNumber { position: 4, .. })
+    ));
+    assert!(matches!(
+      parse_duration("P1H"),
+      Err(DurationError::UnknownUnit { position: 2, .. })
+    ));
+    assert!(matches!(
+      parse_duration("99999999999999999999s"),
+      Err(DurationError::Overflow { .. })
+    ));
+    assert!(matches!(
+      parse_duration("0h"),
+      Err(DurationError::Zero { .. })
+    ));
+  }
+
+  #[test]
+  fn test_time_param_at_most() {
+    let time = TimeParam::from_param("2d").unwrap();
+    assert!(time.is_more_than_a_day);
+    assert!(matches!(
+      time.at_most(DAY),
+      Err(DurationError::TooLong { max: DAY, .. })
+    ));
+    assert!(TimeParam::from_param("1d").unwrap().at_most(DAY).is_ok());
+  }
+}
@@ -0,0 +1,190 @@
+use crate::core::Record;
+use crate::ids::{IdCapacity, IdStats};
+use crate::index::MetadataIndex;
+use crate::store::PasteStore;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard};
+
+/// What happens to an upload that doesn't fit in the budget anymore.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaPolicy {
+  /// the upload is refused with a 507
+  Reject,
+  /// the pastes closest to expiring are deleted until the upload fits
+  #[serde(rename = "evict")]
+  EvictSoonestExpiring,
+}
+
+impl QuotaPolicy {
+  /// policy => `reject` | `evict`
+  pub fn parse(policy: &str) -> Result<Self, String> {
+    match policy {
+      "reject" => Ok(QuotaPolicy::Reject),
+      "evict" => Ok(QuotaPolicy::EvictSoonestExpiring),
+      _ => Err(format!(
+        "expected `reject` or `evict` as the quota policy but found ({})",
+        policy
+      )),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum QuotaError {
+  /// the upload can't fit in the budget
+  Full,
+  Other(Box<dyn Error>),
+}
+
+impl fmt::Display for QuotaError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      QuotaError::Full => write!(f, "storage quota exceeded"),
+      QuotaError::Other(e) => write!(f, "{}", e),
+    }
+  }
+}
+
+impl Error for QuotaError {}
+
+/// Number of records looked at per round of eviction.
+const EVICTION_BATCH: u32 = 32;
+
+/// Total byte budget of the stored pastes, their size being what's written to the store
+/// (after compression), as tracked by `MetadataIndex::used_bytes`.
+pub struct StorageQuota {
+  pub budget: u64,
+  pub policy: QuotaPolicy,
+  /// uploads are admitted one at a time, so two of them can't both take the last free bytes
+  lock: Mutex<()>,
+}
+
+impl StorageQuota {
+  pub fn new(budget: u64, policy: QuotaPolicy) -> Self {
+    StorageQuota {
+      budget,
+      policy,
+      lock: Mutex::new(()),
+    }
+  }
+
+  /// Makes room for an upload of `incoming` bytes, evicting pastes if the policy allows it.
+  ///
+  /// The returned guard has to be held until the upload's record is in the index, so the
+  /// space it was admitted for is accounted before the next upload gets checked.
+  pub fn admit(
+    &self,
+    store: &dyn PasteStore,
+    index: &MetadataIndex,
+    incoming: u64,
+  ) -> Result<MutexGuard<'_, ()>, QuotaError> {
+    let guard = self.lock.lock().expect("storage quota lock was poisoned");
+
+    if incoming > self.budget {
+      return Err(QuotaError::Full);
+    }
+
+    while index.used_bytes() + incoming > self.budget {
+      if self.policy == QuotaPolicy::Reject {
+        return Err(QuotaError::Full);
+      }
+
+      let records = index
+        .soonest_expiring(EVICTION_BATCH)
+        .map_err(|e| QuotaError::Other(Box::new(e)))?;
+      if records.is_empty() {
+        return Err(QuotaError::Full);
+      }
+
+      for record in records {
+        if index.used_bytes() + incoming <= self.budget {
+          break;
+        }
+
+        Record::delete_record_and_file(store, index, &record.key).map_err(QuotaError::Other)?;
+      }
+    }
+
+    Ok(guard)
+  }
+}
+
+/// Body of `GET /stats`.
+#[derive(Debug, Serialize)]
+pub struct StorageStats {
+  pub pastes: u64,
+  pub used_bytes: u64,
+  /// `None` when no quota is configured
+  pub quota_bytes: Option<u64>,
+  pub quota_policy: Option<QuotaPolicy>,
+  pub ids: IdStats,
+}
+
+impl StorageStats {
+  pub fn collect(
+    index: &MetadataIndex,
+    quota: Option<&StorageQuota>,
+    ids: &IdCapacity,
+  ) -> Result<Self, Box<dyn Error>> {
+    let pastes = index.count()?;
+
+    Ok(StorageStats {
+      pastes,
+      used_bytes: index.used_bytes(),
+      quota_bytes: quota.map(|quota| quota.budget),
+      quota_policy: quota.map(|quota| quota.policy),
+      ids: ids.stats(pastes),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::store::MemoryStore;
+
+  fn stored(store: &MemoryStore, index: &MetadataIndex, key: &str, expiry: u64, size: usize) {
+    let mut record = Record::new(key.to_string(), expiry);
+    record.size = size as u64;
+    store.put(key, &vec![0; size]).unwrap();
+    index.insert(&record).unwrap();
+  }
+
+  #[test]
+  fn test_reject_policy() {
+    let store = MemoryStore::new();
+    let index = MetadataIndex::open_in_memory().unwrap();
+    let quota = StorageQuota::new(100, QuotaPolicy::Reject);
+    stored(&store, &index, "aaaa", 60, 80);
+
+    assert!(quota.admit(&store, &index, 20).is_ok());
+    assert!(matches!(
+      quota.admit(&store, &index, 21),
+      Err(QuotaError::Full)
+    ));
+    assert!(store.exists("aaaa"));
+  }
+
+  #[test]
+  fn test_evict_soonest_expiring_policy() {
+    let store = MemoryStore::new();
+    let index = MetadataIndex::open_in_memory().unwrap();
+    let quota = StorageQuota::new(100, QuotaPolicy::EvictSoonestExpiring);
+    stored(&store, &index, "late", 600, 40);
+    stored(&store, &index, "soon", 60, 40);
+
+    assert!(quota.admit(&store, &index, 50).is_ok());
+    assert!(!store.exists("soon"));
+    assert!(!index.contains("soon").unwrap());
+    assert!(store.exists("late"));
+    assert_eq!(40, index.used_bytes());
+
+    assert!(matches!(
+      quota.admit(&store, &index, 101),
+      Err(QuotaError::Full)
+    ));
+  }
+}
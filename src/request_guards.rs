@@ -1,11 +1,21 @@
+use crate::ids::{IdReservation, UniqueID, RESERVED_HEADERS};
+use crate::param_guards::Slug;
+use crate::CustomConfig;
 use rocket::http::Status;
 use rocket::outcome::Outcome;
 use rocket::request::FromRequest;
+use rocket::tokio::task;
+use std::sync::Arc;
 
-#[derive(Debug, Default)]
+/// Only the upload routes take this guard, so only their requests get an ID allocated.
+#[derive(Debug)]
 pub struct UploadRequestGuard {
-  pub id: String,
+  /// the ID to store the paste at, or why the slug asked for can't be it
+  pub id: Result<IdReservation, (Status, String)>,
   pub clear_expired_keys_from_cache: bool,
+  pub content_type: Option<String>,
+  /// uploads don't need an API key, only some of their options do, see `ApiKey`
+  pub api_key: Result<ApiKey, (Status, &'static str)>,
 }
 
 #[rocket::async_trait]
@@ -15,29 +25,189 @@ impl<'r> FromRequest<'r> for UploadRequestGuard {
   async fn from_request(
     request: &'r rocket::Request<'_>,
   ) -> rocket::request::Outcome<Self, Self::Error> {
-    let mut tmp = UploadRequestGuard {
+    // nothing reads these anymore, but a client sending them is up to no good
+    if RESERVED_HEADERS
+      .iter()
+      .any(|name| request.headers().contains(*name))
+    {
+      return Outcome::Failure((Status::BadRequest, "reserved header in the request"));
+    }
+
+    let allocator = match request.rocket().state::<Arc<UniqueID>>() {
+      Some(allocator) => allocator.clone(),
+      None => {
+        return Outcome::Failure((
+          Status::InternalServerError,
+          "Something went wrong while parsing the ID",
+        ))
+      }
+    };
+
+    let clear_expired_keys_from_cache = allocator.count_upload();
+    if clear_expired_keys_from_cache {
+      let allocator = allocator.clone();
+      task::spawn_blocking(move || allocator.reload());
+    }
+
+    let id = match request.query_value::<&str>("slug") {
+      Some(Ok(value)) => match Slug::parse(value) {
+        Ok(slug) => allocate(allocator, Some(slug)).await,
+        Err(e) => Err((Status::UnprocessableEntity, e)),
+      },
+      Some(Err(e)) => Err((Status::UnprocessableEntity, e.to_string())),
+      None => allocate(allocator, None).await,
+    };
+
+    Outcome::Success(UploadRequestGuard {
+      id,
+      clear_expired_keys_from_cache,
+      content_type: request.content_type().map(|ct| ct.to_string()),
+      api_key: ApiKey::check(request),
+    })
+  }
+}
+
+/// `UniqueID::allocate_for`, off the async workers
+async fn allocate(
+  allocator: Arc<UniqueID>,
+  slug: Option<Slug>,
+) -> Result<IdReservation, (Status, String)> {
+  task::spawn_blocking(move || UniqueID::allocate_for(allocator, slug))
+    .await
+    .unwrap_or_else(|e| Err((Status::InternalServerError, e.to_string())))
+}
+
+/// What the client announced in `Accept-Encoding`, only gzip matters to us.
+#[derive(Debug, Default)]
+pub struct AcceptEncoding {
+  pub gzip: bool,
+}
+
+impl AcceptEncoding {
+  /// value => `gzip, deflate;q=0.5` | `*` | `gzip;q=0`
+  pub fn parse(value: &str) -> Self {
+    let mut tmp = AcceptEncoding {
       ..Default::default()
     };
-    let ids: Vec<&str> = request.headers().get("unique-pastebin-id").collect();
-    let clear_cache: Vec<&str> = request
-      .headers()
-      .get("time-to-clear-expired-keys")
-      .collect();
 
-    if clear_cache.len() > 0 {
-      if clear_cache[0].contains("yes") {
-        tmp.clear_expired_keys_from_cache = true;
+    for coding in value.split(',') {
+      let mut parts = coding.split(';').map(|part| part.trim());
+      let name = parts.next().unwrap_or_default();
+      let refused = parts.any(|param| {
+        param
+          .strip_prefix("q=")
+          .and_then(|q| q.parse::<f32>().ok())
+          .map(|q| q <= 0.0)
+          .unwrap_or(false)
+      });
+
+      if name.eq_ignore_ascii_case("gzip") || name == "*" {
+        tmp.gzip = !refused;
+        if name != "*" {
+          break;
+        }
       }
     }
 
-    if ids.len() > 0 {
-      tmp.id = ids[0].to_string();
-      return Outcome::Success(tmp);
+    tmp
+  }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AcceptEncoding {
+  type Error = &'static str;
+
+  async fn from_request(
+    request: &'r rocket::Request<'_>,
+  ) -> rocket::request::Outcome<Self, Self::Error> {
+    let values: Vec<&str> = request.headers().get("Accept-Encoding").collect();
+    Outcome::Success(AcceptEncoding::parse(&values.join(",")))
+  }
+}
+
+/// Proof the request carried one of the configured API keys, as `Authorization: Bearer <key>`
+/// or `X-Api-Key: <key>`. Without any keys configured every request is refused.
+#[derive(Debug)]
+pub struct ApiKey;
+
+impl ApiKey {
+  /// the key given by the request, if any
+  fn presented<'r>(request: &'r rocket::Request<'_>) -> Option<&'r str> {
+    let bearer = request
+      .headers()
+      .get_one("Authorization")
+      .and_then(|value| value.strip_prefix("Bearer "));
+
+    bearer
+      .or_else(|| request.headers().get_one("X-Api-Key"))
+      .map(|key| key.trim())
+  }
+
+  /// compares every byte whatever the outcome, so the time taken doesn't leak the key
+  pub fn matches(configured: &str, presented: &str) -> bool {
+    configured.len() == presented.len()
+      && configured
+        .bytes()
+        .zip(presented.bytes())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+  }
+
+  /// 401 without a key, 403 with one that isn't configured
+  fn check(request: &rocket::Request<'_>) -> Result<Self, (Status, &'static str)> {
+    let api_keys = match request.rocket().state::<CustomConfig>() {
+      Some(custom_config) => &custom_config.api_keys,
+      None => return Err((Status::InternalServerError, "missing configuration")),
+    };
+
+    let presented = match ApiKey::presented(request) {
+      Some(presented) => presented,
+      None => return Err((Status::Unauthorized, "missing API key")),
+    };
+
+    if api_keys
+      .iter()
+      .any(|configured| ApiKey::matches(configured, presented))
+    {
+      return Ok(ApiKey);
     }
 
-    Outcome::Failure((
-      Status::InternalServerError,
-      "Something went wrong while parsing the ID",
-    ))
+    Err((Status::Forbidden, "invalid API key"))
+  }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
+  type Error = &'static str;
+
+  async fn from_request(
+    request: &'r rocket::Request<'_>,
+  ) -> rocket::request::Outcome<Self, Self::Error> {
+    match ApiKey::check(request) {
+      Ok(api_key) => Outcome::Success(api_key),
+      Err(failure) => Outcome::Failure(failure),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_accept_encoding() {
+    assert!(AcceptEncoding::parse("gzip, deflate, br").gzip);
+    assert!(AcceptEncoding::parse("deflate;q=1.0, *;q=0.5").gzip);
+    assert!(!AcceptEncoding::parse("gzip;q=0, *").gzip);
+    assert!(!AcceptEncoding::parse("identity").gzip);
+    assert!(!AcceptEncoding::parse("").gzip);
+  }
+
+  #[test]
+  fn test_api_key_matches() {
+    assert!(ApiKey::matches("s3cr3t", "s3cr3t"));
+    assert!(!ApiKey::matches("s3cr3t", "s3cr3T"));
+    assert!(!ApiKey::matches("s3cr3t", "s3cr3"));
+    assert!(!ApiKey::matches("s3cr3t", ""));
   }
 }
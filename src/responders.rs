@@ -0,0 +1,194 @@
+use crate::core::Record;
+use crate::store::PasteReader;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::tokio::io::{self, AsyncWriteExt, DuplexStream};
+use rocket::tokio::runtime::Handle;
+use rocket::tokio::task;
+use std::io::{ErrorKind, Read};
+
+/// bytes of a paste read from the store at a time while it's streamed out
+const STREAM_CHUNK: usize = 16 * 1024;
+
+/// max-age for permanent pastes => a year, the longest HTTP caches are meant to keep anything
+const PERMANENT_MAX_AGE: i64 = 31_536_000;
+
+/// When a paste goes away, told to clients through the headers of `PasteContent` and
+/// `UploadResponse`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PasteExpiry {
+  At(DateTime<Utc>),
+  Never,
+}
+
+impl PasteExpiry {
+  pub fn of(record: &Record) -> Self {
+    match record.permanent {
+      true => PasteExpiry::Never,
+      false => PasteExpiry::At(Utc::now() + Duration::seconds(record.remaining_time_to_expiry())),
+    }
+  }
+
+  /// `X-Paste-Expires-At` => RFC 3339 timestamp | `never`
+  fn header(&self) -> Header<'static> {
+    let value = match self {
+      PasteExpiry::At(expires_at) => expires_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+      PasteExpiry::Never => "never".to_string(),
+    };
+
+    Header::new("X-Paste-Expires-At", value)
+  }
+
+  /// `Expires` and `Cache-Control`, so HTTP caches drop the paste when we do. `cacheable` is
+  /// `false` for pastes limited in reads, a cached copy would serve reads we never count.
+  fn caching_headers(&self, cacheable: bool) -> Vec<Header<'static>> {
+    if !cacheable {
+      return vec![Header::new("Cache-Control", "no-store")];
+    }
+
+    match self {
+      PasteExpiry::At(expires_at) => vec![
+        Header::new(
+          "Expires",
+          expires_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+        ),
+        Header::new(
+          "Cache-Control",
+          format!(
+            "max-age={}",
+            (*expires_at - Utc::now()).num_seconds().max(0)
+          ),
+        ),
+      ],
+      PasteExpiry::Never => vec![Header::new(
+        "Cache-Control",
+        format!("max-age={}", PERMANENT_MAX_AGE),
+      )],
+    }
+  }
+}
+
+/// Raw paste content. Unlike `Vec<u8>` it doesn't force a `Content-Type` onto the response.
+pub struct PasteContent {
+  /// read from the store as the response goes out, see `PasteStore::open`
+  pub body: PasteReader,
+  /// set when `body` is sent still compressed
+  pub content_encoding: Option<&'static str>,
+  pub content_type: Option<ContentType>,
+  /// an envelope encrypted in the browser, the HTML view looks for this to decrypt it
+  pub client_encrypted: bool,
+  pub expiry: PasteExpiry,
+  /// see `PasteExpiry::caching_headers`
+  pub cacheable: bool,
+}
+
+impl<'r> Responder<'r, 'static> for PasteContent {
+  fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+    let mut response = Response::build();
+    response
+      .streamed_body(stream(self.body))
+      .header(Header::new("Vary", "Accept-Encoding"));
+
+    if let Some(encoding) = self.content_encoding {
+      response.header(Header::new("Content-Encoding", encoding));
+    }
+
+    if let Some(content_type) = self.content_type {
+      response.header(content_type);
+    }
+
+    if self.client_encrypted {
+      response.header(Header::new("X-Paste-Encryption", "client"));
+    }
+
+    response.header(self.expiry.header());
+    for header in self.expiry.caching_headers(self.cacheable) {
+      response.header(header);
+    }
+
+    response.ok()
+  }
+}
+
+/// Copies the paste into the response on a blocking thread, reading from the store doesn't
+/// hold up the async workers then.
+fn stream(mut body: PasteReader) -> DuplexStream {
+  let (mut writer, reader) = io::duplex(STREAM_CHUNK);
+  let handle = Handle::current();
+
+  task::spawn_blocking(move || {
+    let mut chunk = vec![0; STREAM_CHUNK];
+    loop {
+      let read = match body.read(&mut chunk) {
+        Ok(0) => return,
+        Ok(read) => read,
+        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+        Err(e) => {
+          println!("Error while streaming a paste. Error: {}", e);
+          return;
+        }
+      };
+
+      // the client went away
+      if handle.block_on(writer.write_all(&chunk[..read])).is_err() {
+        return;
+      }
+    }
+  });
+
+  reader
+}
+
+/// Answer to an upload, the URL of the paste or what went wrong.
+pub struct UploadResponse {
+  pub status: Status,
+  pub body: String,
+  /// set once the paste is stored
+  pub expiry: Option<PasteExpiry>,
+}
+
+impl From<(Status, String)> for UploadResponse {
+  fn from((status, body): (Status, String)) -> Self {
+    UploadResponse {
+      status,
+      body,
+      expiry: None,
+    }
+  }
+}
+
+impl<'r> Responder<'r, 'static> for UploadResponse {
+  fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+    let mut response = Response::build_from((self.status, self.body).respond_to(request)?);
+
+    if let Some(expiry) = self.expiry {
+      response.header(expiry.header());
+    }
+
+    response.ok()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::TimeZone;
+
+  #[test]
+  fn test_expiry_headers() {
+    let expiry = PasteExpiry::At(Utc.ymd(2026, 11, 1).and_hms(8, 30, 0));
+    assert_eq!("2026-11-01T08:30:00Z", expiry.header().value());
+
+    let caching = expiry.caching_headers(true);
+    assert_eq!("Expires", caching[0].name());
+    assert_eq!("Sun, 01 Nov 2026 08:30:00 GMT", caching[0].value());
+
+    assert_eq!("never", PasteExpiry::Never.header().value());
+    assert_eq!(
+      "no-store",
+      PasteExpiry::Never.caching_headers(false)[0].value()
+    );
+  }
+}
@@ -0,0 +1,961 @@
+use crate::codec;
+use crate::core::{self, Record};
+use crate::envelope::ClientEnvelope;
+use crate::expiry::ExpiryScheduler;
+use crate::ids::{IdCapacity, UniqueID};
+use crate::index::{MetadataIndex, SharedMetadataIndex};
+use crate::param_guards::{
+  parse_duration, parse_expires_at, parse_flag, parse_max_views, DurationError, TimeParam,
+  UploadQuery, ID,
+};
+use crate::quota::{QuotaError, StorageQuota, StorageStats};
+use crate::request_guards::{AcceptEncoding, ApiKey, UploadRequestGuard};
+use crate::responders::{PasteContent, PasteExpiry, UploadResponse};
+use crate::store::{claim_marker, PasteReader, PasteStore, SharedPasteStore};
+use crate::{handle_err, util, CustomConfig};
+use chrono::Utc;
+use r_cache::cache::Cache;
+use rocket::data::ToByteUnit;
+use rocket::http::{ContentType, Status};
+use rocket::response::content::{Html, Json};
+use rocket::tokio::task;
+use rocket::{get, head, post, routes, Build, Data, Rocket, State};
+use std::io::{self, Read};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How the uploaded paste should be kept, on top of its content.
+struct UploadOptions {
+  expiry_in_seconds: u64,
+  /// the body is an envelope encrypted in the browser, see `envelope::ClientEnvelope`
+  client_encrypted: bool,
+  /// deleted as soon as it's read for the first time
+  burn_after_reading: bool,
+  /// expires after that many reads
+  max_views: Option<u64>,
+  /// sliding expiry, `expiry_in_seconds` is then the first idle window
+  idle_window: Option<u64>,
+  /// how long a sliding expiry can keep the paste around at most, from its upload
+  expiry_ceiling: Option<u64>,
+  /// never expires, `expiry_in_seconds` is ignored then. Requires an API key
+  permanent: bool,
+}
+
+impl UploadOptions {
+  fn expiring_in(expiry_in_seconds: u64) -> Self {
+    UploadOptions {
+      expiry_in_seconds,
+      client_encrypted: false,
+      burn_after_reading: false,
+      max_views: None,
+      idle_window: None,
+      expiry_ceiling: None,
+      permanent: false,
+    }
+  }
+
+  /// the options given in the query of the upload routes, `relative_expiry` being the
+  /// `TimeParam` of the route, if it has one
+  fn from_query(
+    relative_expiry: Option<u64>,
+    query: &UploadQuery,
+    custom_config: &CustomConfig,
+  ) -> Result<Self, String> {
+    let permanent = match query.permanent {
+      Some(value) => parse_flag("permanent", value)?,
+      None => false,
+    };
+
+    let burn_after_reading = match query.burn {
+      Some(value) => parse_flag("burn", value)?,
+      None => false,
+    };
+
+    // the first read deletes the paste, there's nothing left to count or slide
+    if burn_after_reading && (query.max_views.is_some() || query.idle.is_some()) {
+      return Err("a `burn` paste can't be given `max_views` or `idle`".to_string());
+    }
+
+    if permanent {
+      if relative_expiry.is_some() || query.expires_at.is_some() || query.idle.is_some() {
+        return Err("a `permanent` paste can't be given an expiry".to_string());
+      }
+
+      return Ok(UploadOptions {
+        max_views: query.max_views.map(parse_max_views).transpose()?,
+        burn_after_reading,
+        permanent,
+        ..UploadOptions::expiring_in(0)
+      });
+    }
+
+    let expiry_in_seconds = match (relative_expiry, query.expires_at) {
+      (Some(_), Some(_)) => {
+        return Err("either give a relative expiry or `expires_at`, not both".to_string())
+      }
+      (Some(expiry), None) => expiry,
+      (None, Some(expires_at)) => parse_expires_at(expires_at, custom_config.max_retention)?,
+      (None, None) => core::DEFAULT_EXPIRY,
+    };
+
+    let max_views = query.max_views.map(parse_max_views).transpose()?;
+
+    // with an idle window, the expiry given otherwise is the hard ceiling
+    let idle_window = match query.idle {
+      Some(idle) => parse_duration(idle).map_err(|e| format!("`idle`: {}", e))?,
+      None => {
+        return Ok(UploadOptions {
+          max_views,
+          burn_after_reading,
+          ..UploadOptions::expiring_in(expiry_in_seconds)
+        })
+      }
+    };
+
+    if idle_window > expiry_in_seconds {
+      return Err(format!(
+        "`idle` can't be longer than the expiry of the paste, {} seconds",
+        expiry_in_seconds
+      ));
+    }
+
+    Ok(UploadOptions {
+      max_views,
+      idle_window: Some(idle_window),
+      expiry_ceiling: Some(expiry_in_seconds),
+      ..UploadOptions::expiring_in(idle_window)
+    })
+  }
+}
+
+/// Writes the content and logs its record, as one unit: the content and its metadata land
+/// together or not at all. Whatever a crash in between leaves behind is cleaned up by
+/// `util::remove_orphaned_pastes` on startup.
+///
+/// Blocks on the store, a round trip away for shared ones, so it's run off the async workers.
+fn store_paste(
+  quota: Option<&StorageQuota>,
+  store: &dyn PasteStore,
+  metadata_index: &MetadataIndex,
+  record: &Record,
+  content: &[u8],
+) -> Result<(), (Status, String)> {
+  // held until the record is logged, so the space is accounted before the next upload
+  let _admitted = match quota {
+    Some(quota) => match quota.admit(store, metadata_index, content.len() as u64) {
+      Ok(guard) => Some(guard),
+      Err(QuotaError::Full) => {
+        return Err((Status::InsufficientStorage, QuotaError::Full.to_string()))
+      }
+      Err(e) => return Err((Status::InternalServerError, e.to_string())),
+    },
+    None => None,
+  };
+
+  // the content goes first, a record is never left pointing at nothing. Content without a
+  // record is swept by `util::remove_orphaned_pastes` once it's old enough
+  if let Err(e) = store.put(&record.key, content) {
+    return Err((Status::InternalServerError, e.to_string()));
+  }
+
+  if let Err(e) = store.put_record(record) {
+    // deletes the record's copy too, in case it got written before failing
+    handle_err!(
+      Record::delete_file(store, &record.key),
+      "Error while rolling back the content of a failed upload",
+      {}
+    );
+    return Err((Status::InternalServerError, e.to_string()));
+  }
+
+  let log_resp = record.log(metadata_index).map_err(|e| e.to_string());
+
+  if let Err(e) = log_resp {
+    handle_err!(
+      Record::delete_file(store, &record.key),
+      "Error while rolling back the content of a failed upload",
+      {}
+    );
+    return Err((Status::InternalServerError, e));
+  }
+
+  Ok(())
+}
+
+async fn abstracted_upload_functionality(
+  upload_request: UploadRequestGuard,
+  custom_config: &CustomConfig,
+  paste: Data<'_>,
+  store: &SharedPasteStore,
+  metadata_index: &SharedMetadataIndex,
+  cache: &State<Cache<String, String>>,
+  options: UploadOptions,
+) -> UploadResponse {
+  if options.permanent {
+    if let Err((status, e)) = upload_request.api_key {
+      return (status, format!("permanent pastes need an API key: {}", e)).into();
+    }
+  }
+
+  let reservation = match upload_request.id {
+    Ok(reservation) => reservation,
+    Err(e) => return e.into(),
+  };
+
+  let url = format!(
+    "{host}/{id}",
+    host = custom_config.exposable_url,
+    id = reservation.id()
+  );
+
+  if upload_request.clear_expired_keys_from_cache {
+    cache.remove_expired().await;
+  }
+
+  let val = match paste.open(128.kibibytes()).into_bytes().await {
+    Ok(val) => val,
+    Err(e) => return (Status::BadRequest, e.to_string()).into(),
+  };
+
+  // nothing gets stored for a paste that got cut off at the limit
+  if !val.is_complete() {
+    return (
+      Status::PayloadTooLarge,
+      format!("paste exceeds the limit of {}", 128.kibibytes()),
+    )
+      .into();
+  }
+
+  let content = if options.client_encrypted {
+    // ciphertext doesn't compress, and we only ever hand it back untouched
+    match ClientEnvelope::validate(&val.into_inner()) {
+      Ok(envelope) => codec::encode(&envelope, false),
+      Err(e) => return (Status::UnprocessableEntity, e).into(),
+    }
+  } else {
+    codec::encode(&val.into_inner(), custom_config.compress)
+  };
+
+  let content = match content {
+    Ok(content) => content,
+    Err(e) => return (Status::InternalServerError, e.to_string()).into(),
+  };
+
+  let id = reservation.id().to_string();
+  let mut record = match options.permanent {
+    true => Record::permanent(id.clone()),
+    false => Record::new(id.clone(), options.expiry_in_seconds),
+  };
+  record.size = content.len() as u64;
+  record.content_type = upload_request.content_type;
+  record.client_encrypted = options.client_encrypted;
+  record.burn_after_reading = options.burn_after_reading;
+  record.remaining_views = options.max_views;
+  record.idle_window = options.idle_window;
+  record.expiry_ceiling = options.expiry_ceiling;
+
+  let stored = {
+    let quota = custom_config.quota.clone();
+    let store = store.clone();
+    let metadata_index = metadata_index.clone();
+    let record = record.clone();
+    task::spawn_blocking(move || {
+      store_paste(
+        quota.as_deref(),
+        store.as_ref(),
+        &metadata_index,
+        &record,
+        &content,
+      )
+    })
+    .await
+  };
+
+  match stored {
+    Ok(Ok(())) => {}
+    // dropping the reservation lets go of the ID, a slug can be tried again right away
+    Ok(Err(e)) => return e.into(),
+    Err(e) => return (Status::InternalServerError, e.to_string()).into(),
+  }
+  reservation.keep();
+
+  cache
+    .set(
+      id.clone(),
+      "".to_string(),
+      Some(Duration::from_secs(record.remaining_time_to_expiry() as u64)),
+    )
+    .await;
+
+  // the browser appends `#<key>` itself, the key never reaches us
+  let body = match options.client_encrypted {
+    true => format!("{}/view", url),
+    false => url,
+  };
+
+  UploadResponse {
+    status: Status::Ok,
+    body,
+    expiry: Some(PasteExpiry::of(&record)),
+  }
+}
+
+#[get("/")]
+fn index() -> &'static str {
+  "
+    USAGE
+
+      POST /
+
+          accepts raw data in the body of the request and responds with a URL of
+          a page containing the body's content, expiring in a week
+
+      POST /<time>
+
+          same as `POST /`, expiring after `<time>` instead: `36h`, `1w2d`, `90m`, `2mo`
+          or an ISO 8601 duration like `P1DT12H`, up to the maximum retention
+
+      POST /private
+
+          accepts a JSON envelope encrypted in the browser (iv, salt, ciphertext and the
+          cipher params) and stores it without ever seeing the key. Responds with the URL
+          of the view page, append `#<key>` to it to decrypt the paste in the browser
+
+      POST /?max_views=<n>
+      POST /<time>?max_views=<n>
+
+          the paste expires after `n` reads, or its expiry, whichever comes first.
+          Reading it afterwards responds with 410 Gone. Works with `/private` too
+
+      POST /?expires_at=<timestamp>
+
+          the paste expires at the given RFC 3339 timestamp (`2026-11-01T00:00:00Z`) or
+          at the start of the given day in UTC (`2026-11-01`) instead of in a week.
+          Works with `/private` too
+
+      POST /?idle=<time>
+      POST /<time>?idle=<time>
+
+          sliding expiry: the paste expires once it goes unread for `idle` (same format
+          as `POST /<time>`), but never later than the expiry it would have had otherwise.
+          Combines with `expires_at` and `max_views`, works with `/private` too
+
+      POST /?permanent
+
+          the paste never expires. Requires one of the configured API keys, as
+          `Authorization: Bearer <key>` or `X-Api-Key: <key>`. Combines with
+          `max_views`, works with `/private` too
+
+      POST /?slug=<slug>
+
+          the paste gets the given ID instead of a random one, like `deploy-checklist`:
+          3 to 48 letters, digits and single hyphens, route names aside. Responds with
+          409 Conflict when a paste has it already. Works with every upload route
+
+      POST /burn
+      POST /burn/<time>
+      POST /?burn
+
+          same as `POST /` and `POST /<time>`, but the paste is deleted as soon as it's
+          read for the first time. Reading it again responds with 410 Gone. Takes the
+          same query as the other upload routes, but not `max_views` or `idle`, works
+          with `/private` too
+
+      GET /<id>
+
+          retrieves the content for the paste with id `<id>`. `X-Paste-Expires-At` tells
+          when it expires (RFC 3339, or `never`), `Expires` and `Cache-Control` keep HTTP
+          caches in line with it. Uploads answer with `X-Paste-Expires-At` too. A `HEAD`
+          request doesn't count as a read
+
+      POST /<id>/extend/<time>
+
+          pushes the expiry of the paste with id `<id>` back by `<time>` (same format as
+          `POST /<time>`), up to the maximum retention. Requires one of the configured
+          API keys, as `Authorization: Bearer <key>` or `X-Api-Key: <key>`
+
+      GET /<id>/view
+
+          HTML page showing the paste, decrypting client-encrypted ones with the key
+          from the URL fragment
+
+      GET /stats
+
+          JSON with the number of stored pastes, the bytes they take up, the
+          storage quota, if one is configured, and how full the space of random IDs
+          is (`ids`), along with the ID length it grew to. `near_capacity` turns true
+          once the IDs can't grow any longer, uploads answer 503 when they run out
+    "
+}
+
+#[post("/?<query..>", data = "<paste>")]
+async fn upload(
+  query: UploadQuery<'_>,
+  paste: Data<'_>,
+  upload_request: UploadRequestGuard,
+  store: &State<SharedPasteStore>,
+  metadata_index: &State<SharedMetadataIndex>,
+  cache: &State<Cache<String, String>>,
+  custom_config: &State<CustomConfig>,
+) -> UploadResponse {
+  let options = match UploadOptions::from_query(None, &query, custom_config) {
+    Ok(options) => options,
+    Err(e) => return (Status::UnprocessableEntity, e).into(),
+  };
+
+  abstracted_upload_functionality(
+    upload_request,
+    custom_config.inner(),
+    paste,
+    store,
+    metadata_index,
+    cache,
+    options,
+  )
+  .await
+}
+
+#[post("/private?<query..>", data = "<paste>")]
+async fn private_upload(
+  query: UploadQuery<'_>,
+  paste: Data<'_>,
+  upload_request: UploadRequestGuard,
+  store: &State<SharedPasteStore>,
+  metadata_index: &State<SharedMetadataIndex>,
+  cache: &State<Cache<String, String>>,
+  custom_config: &State<CustomConfig>,
+) -> UploadResponse {
+  let options = match UploadOptions::from_query(None, &query, custom_config) {
+    Ok(options) => options,
+    Err(e) => return (Status::UnprocessableEntity, e).into(),
+  };
+
+  abstracted_upload_functionality(
+    upload_request,
+    custom_config.inner(),
+    paste,
+    store,
+    metadata_index,
+    cache,
+    UploadOptions {
+      client_encrypted: true,
+      ..options
+    },
+  )
+  .await
+}
+
+#[post("/burn?<query..>", data = "<paste>")]
+async fn burn_upload(
+  query: UploadQuery<'_>,
+  paste: Data<'_>,
+  upload_request: UploadRequestGuard,
+  store: &State<SharedPasteStore>,
+  metadata_index: &State<SharedMetadataIndex>,
+  cache: &State<Cache<String, String>>,
+  custom_config: &State<CustomConfig>,
+) -> UploadResponse {
+  let query = UploadQuery {
+    burn: Some(""),
+    ..query
+  };
+  let options = match UploadOptions::from_query(None, &query, custom_config) {
+    Ok(options) => options,
+    Err(e) => return (Status::UnprocessableEntity, e).into(),
+  };
+
+  abstracted_upload_functionality(
+    upload_request,
+    custom_config.inner(),
+    paste,
+    store,
+    metadata_index,
+    cache,
+    options,
+  )
+  .await
+}
+
+#[post("/burn/<time>?<query..>", data = "<paste>")]
+#[allow(clippy::too_many_arguments)]
+async fn custom_burn_upload(
+  time: Result<TimeParam, DurationError>,
+  query: UploadQuery<'_>,
+  upload_request: UploadRequestGuard,
+  store: &State<SharedPasteStore>,
+  metadata_index: &State<SharedMetadataIndex>,
+  cache: &State<Cache<String, String>>,
+  custom_config: &State<CustomConfig>,
+  paste: Data<'_>,
+) -> UploadResponse {
+  let time = match time.and_then(|time| time.at_most(custom_config.max_retention)) {
+    Ok(time) => time,
+    Err(e) => return (e.status(), e.to_string()).into(),
+  };
+
+  let query = UploadQuery {
+    burn: Some(""),
+    ..query
+  };
+  let options =
+    match UploadOptions::from_query(Some(time.duration.as_secs()), &query, custom_config) {
+      Ok(options) => options,
+      Err(e) => return (Status::UnprocessableEntity, e).into(),
+    };
+
+  abstracted_upload_functionality(
+    upload_request,
+    custom_config.inner(),
+    paste,
+    store,
+    metadata_index,
+    cache,
+    options,
+  )
+  .await
+}
+
+#[get("/<id>")]
+async fn retrieve(
+  id: ID,
+  accept_encoding: AcceptEncoding,
+  store: &State<SharedPasteStore>,
+  metadata_index: &State<SharedMetadataIndex>,
+  cache: &State<Cache<String, String>>,
+) -> Result<PasteContent, Status> {
+  read_paste(id, accept_encoding, store, metadata_index, cache, true).await
+}
+
+/// Answers like `retrieve`, without counting as a read. Rocket would otherwise hand HEAD
+/// requests to `retrieve` as if they were GET ones.
+#[head("/<id>")]
+async fn retrieve_head(
+  id: ID,
+  accept_encoding: AcceptEncoding,
+  store: &State<SharedPasteStore>,
+  metadata_index: &State<SharedMetadataIndex>,
+  cache: &State<Cache<String, String>>,
+) -> Result<PasteContent, Status> {
+  read_paste(id, accept_encoding, store, metadata_index, cache, false).await
+}
+
+/// Counts a read of the paste: claims a burn-after-reading one, takes one of the views of one
+/// with a view limit and slides an idle expiry. Returns `true` for the last read, the caller
+/// deletes the content then.
+///
+/// Blocks on the index and the store, so it's run off the async workers.
+fn take_read(
+  store: &dyn PasteStore,
+  metadata_index: &MetadataIndex,
+  record: &mut Record,
+) -> Result<bool, Status> {
+  let id = record.key.clone();
+
+  // only the first reader of a burn-after-reading paste gets past the claim
+  if record.burn_after_reading {
+    match metadata_index.claim(&id) {
+      Ok(true) => {}
+      Ok(false) => return Err(Status::Gone),
+      Err(_) => return Err(Status::InternalServerError),
+    }
+
+    // the local claim only settles it among the readers of this instance
+    if store.is_shared() {
+      match store.create_marker(&claim_marker(&id)) {
+        Ok(true) => {}
+        Ok(false) => return Err(Status::Gone),
+        Err(_) => return Err(Status::InternalServerError),
+      }
+    }
+    return Ok(true);
+  }
+
+  let mut last_read = false;
+  if record.remaining_views.is_some() {
+    match record.take_view(store, metadata_index) {
+      Ok(Some(views_left)) => last_read = views_left == 0,
+      Ok(None) => return Err(Status::Gone),
+      Err(e) => {
+        println!("Error while counting a view of a paste. Error: {}", e);
+        return Err(Status::InternalServerError);
+      }
+    }
+  }
+
+  if record.idle_window.is_some() && !last_read {
+    let touched = record
+      .touch(metadata_index, Utc::now().timestamp())
+      .and_then(|_| Ok(store.put_record(record)?))
+      .map_err(|e| e.to_string());
+    handle_err!(touched, "Error while sliding the expiry of a paste", {});
+  }
+
+  Ok(last_read)
+}
+
+/// `reading` => whether the request counts as a read, burning the paste, taking one of its
+/// views and sliding its idle expiry
+async fn read_paste(
+  id: ID,
+  accept_encoding: AcceptEncoding,
+  store: &State<SharedPasteStore>,
+  metadata_index: &State<SharedMetadataIndex>,
+  cache: &State<Cache<String, String>>,
+  reading: bool,
+) -> Result<PasteContent, Status> {
+  let mut record = if cache.get(&id.0).await.is_some() {
+    match metadata_index.get(&id.0) {
+      Ok(Some(record)) => record,
+      Ok(None) => return Err(Status::NotFound),
+      Err(_) => return Err(Status::InternalServerError),
+    }
+  } else {
+    // uploaded by another instance sharing the store since this one started
+    let shared_store = store.inner().clone();
+    let shared_index = metadata_index.inner().clone();
+    let key = id.0.clone();
+    let adopted = task::spawn_blocking(move || {
+      util::adopt_shared_record(shared_store.as_ref(), &shared_index, &key)
+        .map_err(|e| e.to_string())
+    })
+    .await;
+    match adopted {
+      Ok(Ok(Some(record))) => {
+        cache
+          .set(
+            id.0.clone(),
+            "".to_string(),
+            Some(Duration::from_secs(record.remaining_time_to_expiry() as u64)),
+          )
+          .await;
+        record
+      }
+      Ok(Ok(None)) => return Err(Status::NotFound),
+      _ => return Err(Status::InternalServerError),
+    }
+  };
+
+  let mut last_read = false;
+  if reading {
+    let shared_store = store.inner().clone();
+    let shared_index = metadata_index.inner().clone();
+    let counted = task::spawn_blocking(move || {
+      let last_read = take_read(shared_store.as_ref(), &shared_index, &mut record);
+      (record, last_read)
+    })
+    .await;
+
+    let (counted, taken) = match counted {
+      Ok(counted) => counted,
+      Err(_) => return Err(Status::InternalServerError),
+    };
+    record = counted;
+    last_read = taken?;
+
+    // the cached entry would otherwise still expire at the end of the previous window
+    if record.idle_window.is_some() && !last_read {
+      cache
+        .set(
+          id.0.clone(),
+          "".to_string(),
+          Some(Duration::from_secs(record.remaining_time_to_expiry() as u64)),
+        )
+        .await;
+    }
+  }
+
+  let reading_store = store.inner().clone();
+  let key = id.0.clone();
+  let opened = task::spawn_blocking(move || -> io::Result<Option<PasteReader>> {
+    let mut content = match reading_store.open(&key)? {
+      Some(content) => content,
+      None => return Ok(None),
+    };
+
+    // the content is deleted right away, it can't be left to stream from the store
+    if last_read {
+      let mut buffered = Vec::new();
+      content.read_to_end(&mut buffered)?;
+      content = Box::new(io::Cursor::new(buffered));
+
+      handle_err!(
+        Record::delete_file(reading_store.as_ref(), &key),
+        "Error while deleting a paste that was read for the last time",
+        {}
+      );
+    }
+    Ok(Some(content))
+  })
+  .await;
+
+  let content = match opened {
+    Ok(Ok(Some(content))) => content,
+    // another instance sharing the store took the last read already
+    Ok(Ok(None)) if record.burn_after_reading || record.remaining_views.is_some() => {
+      return Err(Status::Gone)
+    }
+    Ok(Ok(None)) => return Err(Status::NotFound),
+    _ => return Err(Status::InternalServerError),
+  };
+
+  match codec::decode(content, accept_encoding.gzip) {
+    Ok(decoded) => Ok(PasteContent {
+      body: decoded.body,
+      content_encoding: decoded.content_encoding,
+      // never let anything render or sniff a client-encrypted envelope
+      content_type: if record.client_encrypted {
+        Some(ContentType::JSON)
+      } else {
+        None
+      },
+      client_encrypted: record.client_encrypted,
+      expiry: PasteExpiry::of(&record),
+      // a read an HTTP cache answers for doesn't count, nor slide an idle expiry
+      cacheable: !record.burn_after_reading
+        && record.remaining_views.is_none()
+        && record.idle_window.is_none(),
+    }),
+    Err(_) => Err(Status::InternalServerError),
+  }
+}
+
+#[post("/<id>/extend/<time>")]
+async fn extend(
+  id: ID,
+  time: Result<TimeParam, DurationError>,
+  _api_key: ApiKey,
+  store: &State<SharedPasteStore>,
+  metadata_index: &State<SharedMetadataIndex>,
+  cache: &State<Cache<String, String>>,
+  custom_config: &State<CustomConfig>,
+) -> (Status, String) {
+  // the maximum retention applies to the extended expiry as a whole, checked below
+  let time = match time {
+    Ok(time) => time,
+    Err(e) => return (e.status(), e.to_string()),
+  };
+
+  let mut record = match metadata_index.get(&id.0) {
+    Ok(Some(record)) if record.permanent => {
+      return (Status::Conflict, format!("({}) never expires", id.0))
+    }
+    Ok(Some(record)) if !record.is_key_expired() => record,
+    Ok(_) => return (Status::NotFound, format!("no paste found for ({})", id.0)),
+    Err(e) => return (Status::InternalServerError, e.to_string()),
+  };
+
+  // its content is gone, only the record is left to answer reads with a 410
+  match metadata_index.is_tombstone(&id.0) {
+    Ok(false) => {}
+    Ok(true) => {
+      return (
+        Status::Gone,
+        format!("({}) was read for the last time", id.0),
+      )
+    }
+    Err(e) => return (Status::InternalServerError, e.to_string()),
+  }
+
+  let remaining = record.remaining_time_to_expiry() as u64 + time.duration.as_secs();
+  // the ceiling of a sliding expiry is pushed back too, and is what bounds its retention
+  let furthest = match record.expiry_ceiling {
+    Some(ceiling) => {
+      let left = record.created_timestamp() + ceiling as i64 - Utc::now().timestamp();
+      left.max(0) as u64 + time.duration.as_secs()
+    }
+    None => remaining,
+  };
+  if furthest > custom_config.max_retention {
+    return (
+      Status::UnprocessableEntity,
+      format!(
+        "the paste can't be kept for more than {} days",
+        custom_config.max_retention / 86_400
+      ),
+    );
+  }
+
+  let extended = record
+    .extend(metadata_index, time.duration.as_secs())
+    .map_err(|e| e.to_string());
+  if let Err(e) = extended {
+    return (Status::InternalServerError, e);
+  }
+
+  // Other instances sharing the store keep serving their own copy until their cache entry
+  // runs out at the old expiry, the next read there adopts this one. Their schedulers check
+  // it before deleting the paste, see `ExpiryScheduler`.
+  let shared_store = store.inner().clone();
+  let written = task::spawn_blocking(move || shared_store.put_record(&record)).await;
+  match written {
+    Ok(Ok(())) => {}
+    Ok(Err(e)) => return (Status::InternalServerError, e.to_string()),
+    Err(e) => return (Status::InternalServerError, e.to_string()),
+  }
+
+  cache
+    .set(
+      id.0.clone(),
+      "".to_string(),
+      Some(Duration::from_secs(remaining)),
+    )
+    .await;
+
+  (
+    Status::Ok,
+    format!("{} now expires in {} seconds", id.0, remaining),
+  )
+}
+
+#[get("/stats")]
+fn stats(
+  metadata_index: &State<SharedMetadataIndex>,
+  id_capacity: &State<Arc<IdCapacity>>,
+  custom_config: &State<CustomConfig>,
+) -> Result<Json<String>, Status> {
+  StorageStats::collect(metadata_index, custom_config.quota.as_deref(), id_capacity)
+    .and_then(|stats| Ok(serde_json::to_string(&stats)?))
+    .map(Json)
+    .map_err(|_| Status::InternalServerError)
+}
+
+#[get("/<_id>/view")]
+fn view(_id: ID) -> Html<&'static str> {
+  Html(include_str!("view.html"))
+}
+
+#[post("/<time>?<query..>", data = "<paste>")]
+#[allow(clippy::too_many_arguments)]
+async fn custom_upload(
+  time: Result<TimeParam, DurationError>,
+  query: UploadQuery<'_>,
+  upload_request: UploadRequestGuard,
+  store: &State<SharedPasteStore>,
+  metadata_index: &State<SharedMetadataIndex>,
+  cache: &State<Cache<String, String>>,
+  custom_config: &State<CustomConfig>,
+  paste: Data<'_>,
+) -> UploadResponse {
+  let time = match time.and_then(|time| time.at_most(custom_config.max_retention)) {
+    Ok(time) => time,
+    Err(e) => return (e.status(), e.to_string()).into(),
+  };
+
+  let options =
+    match UploadOptions::from_query(Some(time.duration.as_secs()), &query, custom_config) {
+      Ok(options) => options,
+      Err(e) => return (Status::UnprocessableEntity, e).into(),
+    };
+
+  abstracted_upload_functionality(
+    upload_request,
+    custom_config.inner(),
+    paste,
+    store,
+    metadata_index,
+    cache,
+    options,
+  )
+  .await
+}
+
+/// Opens the index and the store the config points at, catches up on whatever happened while
+/// the service was down and mounts the routes.
+pub async fn build(custom_config: CustomConfig) -> Rocket<Build> {
+  let cache = Cache::<String, String>::new(Some(Duration::from_secs(2 * 60 * 60)));
+
+  let metadata_index: SharedMetadataIndex = match MetadataIndex::open(&custom_config.index_path) {
+    Ok(metadata_index) => Arc::new(metadata_index),
+    Err(e) => panic!("Error while opening the metadata index. Error: {}", e),
+  };
+
+  let store = custom_config.build_store(metadata_index.clone());
+  handle_err!(store, "Error while opening the paste store");
+  let store = store.unwrap();
+
+  // moving the pastes of the flat `upload/<id>` layout happens in the background, the
+  // store finds them at either place meanwhile
+  let storage = custom_config.storage.clone();
+  std::thread::spawn(move || match storage.migrate_legacy_layout() {
+    Ok(0) => {}
+    Ok(count) => println!("Moved {} pastes into the sharded upload layout!", count),
+    Err(e) => println!("Error while migrating the upload layout. Error: {}", e),
+  });
+
+  // one-time import of the records from the old `deletions/<date>.txt` files
+  {
+    let imported = metadata_index.import_deletions_dir("deletions", store.as_ref());
+    handle_err!(imported, "Error while importing the old deletions files");
+    if let Ok(count) = imported {
+      if count > 0 {
+        println!("Imported {} records from the deletions files!", count);
+      }
+    }
+  }
+
+  {
+    let adopted = util::adopt_shared_records(store.as_ref(), &metadata_index);
+    handle_err!(
+      adopted,
+      "Error while adopting the records of the shared store"
+    );
+    if let Ok(count) = adopted {
+      if count > 0 {
+        println!("Adopted {} records from the shared store!", count);
+      }
+    }
+  }
+
+  {
+    let removed = util::remove_orphaned_pastes(store.as_ref(), &metadata_index);
+    handle_err!(removed, "Error while removing the orphaned pastes");
+    if let Ok(count) = removed {
+      if count > 0 {
+        println!("Removed {} pastes without metadata!", count);
+      }
+    }
+  }
+
+  // populating the cache from the saved pastes
+  util::populate_cache_on_first_run(&cache, &metadata_index).await;
+
+  // deletes the expired pastes from here on, starting with the ones that expired while
+  // the service was down
+  let expiry_scheduler = ExpiryScheduler::start(store.clone(), metadata_index.clone());
+  handle_err!(
+    expiry_scheduler,
+    "Error while starting the expiry scheduler"
+  );
+  let expiry_scheduler = expiry_scheduler.unwrap();
+
+  let uid = Arc::new(UniqueID::new(
+    store.clone(),
+    metadata_index.clone(),
+    &custom_config.ids,
+  ));
+  let id_capacity = uid.capacity();
+  rocket::build()
+    .mount(
+      "/",
+      routes![
+        index,
+        upload,
+        private_upload,
+        burn_upload,
+        custom_burn_upload,
+        retrieve,
+        retrieve_head,
+        extend,
+        stats,
+        view,
+        custom_upload
+      ],
+    )
+    .manage(uid)
+    .manage(id_capacity)
+    .manage(expiry_scheduler)
+    .manage(store)
+    .manage(metadata_index)
+    .manage(cache)
+    .manage(custom_config)
+}
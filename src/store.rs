@@ -0,0 +1,401 @@
+use crate::core::Record;
+use crate::s3::{S3Config, S3Store};
+use crate::util::files_in_directory_tree;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Cursor, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Storage for the raw paste bodies, keyed by the paste ID.
+///
+/// Everything that reads or writes a paste's content goes through this trait, so the
+/// storage can be swapped without touching the routes or `core::Record`.
+pub trait PasteStore: Send + Sync {
+  fn put(&self, id: &str, data: &[u8]) -> io::Result<()>;
+
+  /// returns `None` when there is no paste stored under the given ID
+  fn get(&self, id: &str) -> io::Result<Option<Vec<u8>>>;
+
+  /// Like `get`, but hands the paste over as it's read instead of all at once. Stores that
+  /// can't do better read it whole through `get`.
+  fn open(&self, id: &str) -> io::Result<Option<PasteReader>> {
+    Ok(
+      self
+        .get(id)?
+        .map(|data| Box::new(Cursor::new(data)) as PasteReader),
+    )
+  }
+
+  /// returns `true` when something was actually deleted
+  fn delete(&self, id: &str) -> io::Result<bool>;
+
+  fn exists(&self, id: &str) -> bool;
+
+  fn list(&self) -> io::Result<Vec<String>>;
+
+  /// when the paste was last written, `None` when the store can't tell
+  fn modified(&self, _id: &str) -> io::Result<Option<DateTime<Utc>>> {
+    Ok(None)
+  }
+
+  /// `true` when other instances of the service read and write the same storage
+  fn is_shared(&self) -> bool {
+    false
+  }
+
+  /// Keeps a copy of the paste's record next to its content, so the other instances sharing
+  /// the storage can serve and sweep it. A no-op for stores only one instance uses, their
+  /// records live in its `index::MetadataIndex` alone.
+  fn put_record(&self, _record: &Record) -> io::Result<()> {
+    Ok(())
+  }
+
+  /// the copy of the record written by `put_record`, removed by `delete` along with the paste
+  fn get_record(&self, _id: &str) -> io::Result<Option<Record>> {
+    Ok(None)
+  }
+
+  /// IDs of every record written by `put_record`, whether its paste is still there or not
+  fn list_records(&self) -> io::Result<Vec<String>> {
+    Ok(Vec::new())
+  }
+
+  /// Creates the named marker unless it exists already, in one step every instance sharing
+  /// the storage agrees on. Returns `false` when it existed. Stores only one instance uses
+  /// leave this to its `index::MetadataIndex` and always return `true`.
+  fn create_marker(&self, _name: &str) -> io::Result<bool> {
+    Ok(true)
+  }
+
+  /// removes a marker written by `create_marker`, `delete` takes the ones of the paste along
+  fn delete_marker(&self, _name: &str) -> io::Result<()> {
+    Ok(())
+  }
+
+  /// Deletes the content no paste points at that was last written before `older_than`, left
+  /// behind when the process died between writing it and linking a paste to it. Returns the
+  /// number deleted. Only `dedup::DedupStore` keeps content apart from the pastes like that.
+  fn remove_unlinked(&self, _older_than: DateTime<Utc>) -> io::Result<u32> {
+    Ok(0)
+  }
+}
+
+/// marker of the one read of a burn-after-reading paste, see `PasteStore::create_marker`
+pub fn claim_marker(id: &str) -> String {
+  format!("claims/{}", id)
+}
+
+/// Marker of the read that takes the views left of a paste from `views` down by one, see
+/// `core::Record::take_view`.
+pub fn view_marker(id: &str, views: u64) -> String {
+  format!("{}{}", view_markers(id), views)
+}
+
+/// what the names of the view markers of a paste start with
+pub fn view_markers(id: &str) -> String {
+  format!("views/{}/", id)
+}
+
+/// marker of the upload an ID was handed out to, see `ids::UniqueID`
+pub fn id_marker(id: &str) -> String {
+  format!("ids/{}", id)
+}
+
+pub type SharedPasteStore = Arc<dyn PasteStore>;
+
+/// A paste being read, see `PasteStore::open`.
+pub type PasteReader = Box<dyn Read + Send>;
+
+/// Which `PasteStore` implementation the service should run with.
+#[derive(Debug, Clone)]
+pub enum StorageBackend {
+  /// directory holding one file per paste, fanned out over shard subdirectories
+  FileSystem(PathBuf),
+  Memory,
+  /// bucket of an S3-compatible object storage, shared by every instance pointing at it
+  S3(S3Config),
+}
+
+impl StorageBackend {
+  pub fn open(&self) -> io::Result<SharedPasteStore> {
+    match self {
+      StorageBackend::FileSystem(root) => Ok(Arc::new(FileSystemStore::new(root)?)),
+      StorageBackend::Memory => Ok(Arc::new(MemoryStore::new())),
+      StorageBackend::S3(config) => Ok(Arc::new(S3Store::new(config.clone())?)),
+    }
+  }
+
+  /// Moves pastes stored in an older on-disk layout into the current one, returns the
+  /// number of pastes moved.
+  pub fn migrate_legacy_layout(&self) -> io::Result<u32> {
+    match self {
+      StorageBackend::FileSystem(root) => FileSystemStore::new(root)?.migrate_flat_layout(),
+      StorageBackend::Memory | StorageBackend::S3(_) => Ok(0),
+    }
+  }
+}
+
+/// in-flight writes, hidden so they never show up in `list`
+const TEMP_FILE_PREFIX: &str = ".tmp-";
+
+/// Keeps every paste as a file named after its ID inside `root` (`upload/` by default).
+///
+/// The files are fanned out over two levels of subdirectories picked from the SHA-256 of
+/// the ID (`upload/3f/a2/<id>`), so no single directory grows past a few hundred entries
+/// even with millions of pastes. Files left in `root` by the old flat layout are still
+/// found, and `migrate_flat_layout` moves them into place.
+pub struct FileSystemStore {
+  root: PathBuf,
+}
+
+impl FileSystemStore {
+  pub fn new<P: Into<PathBuf>>(root: P) -> io::Result<Self> {
+    let root = root.into();
+    fs::create_dir_all(&root)?;
+
+    // temp files of writes that never finished, the process died in the middle of them
+    for entry in fs::read_dir(&root)? {
+      let entry = entry?;
+      if entry
+        .file_name()
+        .to_string_lossy()
+        .starts_with(TEMP_FILE_PREFIX)
+      {
+        fs::remove_file(entry.path())?;
+      }
+    }
+
+    Ok(FileSystemStore { root })
+  }
+
+  fn path_for(&self, id: &str) -> PathBuf {
+    let digest = Sha256::digest(id.as_bytes());
+    self
+      .root
+      .join(format!("{:02x}", digest[0]))
+      .join(format!("{:02x}", digest[1]))
+      .join(id)
+  }
+
+  fn flat_path_for(&self, id: &str) -> PathBuf {
+    self.root.join(id)
+  }
+
+  fn read(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+      Ok(data) => Ok(Some(data)),
+      Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+      Err(e) => Err(e),
+    }
+  }
+
+  fn remove(path: &Path) -> io::Result<bool> {
+    match fs::remove_file(path) {
+      Ok(_) => Ok(true),
+      Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+      Err(e) => Err(e),
+    }
+  }
+
+  /// Moves the pastes of the old flat `upload/<id>` layout into their shard directories.
+  /// Safe to run while the service is up, returns the number of files moved.
+  pub fn migrate_flat_layout(&self) -> io::Result<u32> {
+    let mut counter: u32 = 0;
+    for entry in fs::read_dir(&self.root)? {
+      let entry = entry?;
+      let id = entry.file_name().to_string_lossy().into_owned();
+      if !entry.file_type()?.is_file() || id.starts_with('.') {
+        continue;
+      }
+
+      let target = self.path_for(&id);
+      if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+      }
+      match fs::rename(entry.path(), target) {
+        Ok(_) => counter += 1,
+        // deleted while we were at it
+        Err(e) if e.kind() == ErrorKind::NotFound => continue,
+        Err(e) => return Err(e),
+      }
+    }
+
+    Ok(counter)
+  }
+}
+
+impl PasteStore for FileSystemStore {
+  /// Writes to a temp file first, fsyncs it and only then renames it into place, so a
+  /// paste is either there completely or not at all.
+  fn put(&self, id: &str, data: &[u8]) -> io::Result<()> {
+    let path = self.path_for(id);
+    let parent = path.parent().unwrap_or(&self.root);
+    fs::create_dir_all(parent)?;
+
+    let temp_path = self.root.join(format!(
+      "{}{}-{:016x}",
+      TEMP_FILE_PREFIX,
+      id,
+      rand::random::<u64>()
+    ));
+
+    let written = (|| {
+      let mut file = File::create(&temp_path)?;
+      file.write_all(data)?;
+      file.sync_all()?;
+      fs::rename(&temp_path, &path)?;
+      // makes the rename itself durable
+      File::open(parent)?.sync_all()
+    })();
+
+    if written.is_err() {
+      let _ = fs::remove_file(&temp_path);
+      return written;
+    }
+
+    // a stale copy left in the flat layout would only waste space
+    FileSystemStore::remove(&self.flat_path_for(id))?;
+    Ok(())
+  }
+
+  fn get(&self, id: &str) -> io::Result<Option<Vec<u8>>> {
+    if let Some(data) = FileSystemStore::read(&self.path_for(id))? {
+      return Ok(Some(data));
+    }
+
+    match FileSystemStore::read(&self.flat_path_for(id))? {
+      Some(data) => Ok(Some(data)),
+      // the migration might have moved the file in between the two reads
+      None => FileSystemStore::read(&self.path_for(id)),
+    }
+  }
+
+  fn delete(&self, id: &str) -> io::Result<bool> {
+    // flat copy first, so a migration moving the file meanwhile can't leave it behind
+    let deleted = FileSystemStore::remove(&self.flat_path_for(id))?;
+    Ok(FileSystemStore::remove(&self.path_for(id))? || deleted)
+  }
+
+  fn exists(&self, id: &str) -> bool {
+    self.path_for(id).is_file() || self.flat_path_for(id).is_file()
+  }
+
+  fn list(&self) -> io::Result<Vec<String>> {
+    files_in_directory_tree(&self.root)
+  }
+}
+
+/// Keeps the pastes in memory only, handy for tests and throwaway instances.
+#[derive(Default)]
+pub struct MemoryStore {
+  pastes: RwLock<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryStore {
+  pub fn new() -> Self {
+    MemoryStore::default()
+  }
+}
+
+fn poisoned<T>(_: T) -> io::Error {
+  io::Error::other("paste store lock was poisoned")
+}
+
+impl PasteStore for MemoryStore {
+  fn put(&self, id: &str, data: &[u8]) -> io::Result<()> {
+    self
+      .pastes
+      .write()
+      .map_err(poisoned)?
+      .insert(id.to_string(), data.to_vec());
+    Ok(())
+  }
+
+  fn get(&self, id: &str) -> io::Result<Option<Vec<u8>>> {
+    Ok(self.pastes.read().map_err(poisoned)?.get(id).cloned())
+  }
+
+  fn delete(&self, id: &str) -> io::Result<bool> {
+    Ok(self.pastes.write().map_err(poisoned)?.remove(id).is_some())
+  }
+
+  fn exists(&self, id: &str) -> bool {
+    self
+      .pastes
+      .read()
+      .map(|pastes| pastes.contains_key(id))
+      .unwrap_or(false)
+  }
+
+  fn list(&self) -> io::Result<Vec<String>> {
+    Ok(
+      self
+        .pastes
+        .read()
+        .map_err(poisoned)?
+        .keys()
+        .cloned()
+        .collect(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn round_trip(store: &dyn PasteStore) {
+    assert!(!store.exists("abcd"));
+    store.put("abcd", b"hello").unwrap();
+    assert!(store.exists("abcd"));
+    assert_eq!(Some(b"hello".to_vec()), store.get("abcd").unwrap());
+    assert_eq!(vec!["abcd".to_string()], store.list().unwrap());
+    assert!(store.delete("abcd").unwrap());
+    assert!(!store.delete("abcd").unwrap());
+    assert_eq!(None, store.get("abcd").unwrap());
+  }
+
+  #[test]
+  fn test_memory_store() {
+    round_trip(&MemoryStore::new());
+  }
+
+  #[test]
+  fn test_file_system_store() {
+    let root = std::env::temp_dir().join(format!("pastebin-store-{}", std::process::id()));
+    round_trip(&FileSystemStore::new(&root).unwrap());
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[test]
+  fn test_migrate_flat_layout() {
+    let root = std::env::temp_dir().join(format!("pastebin-flat-{}", std::process::id()));
+    let store = FileSystemStore::new(&root).unwrap();
+    fs::write(root.join("OLD1"), b"legacy").unwrap();
+
+    assert!(store.exists("OLD1"));
+    assert_eq!(1, store.migrate_flat_layout().unwrap());
+    assert!(!root.join("OLD1").exists());
+    assert_eq!(Some(b"legacy".to_vec()), store.get("OLD1").unwrap());
+    assert_eq!(vec!["OLD1".to_string()], store.list().unwrap());
+
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[test]
+  fn test_stale_temp_files_are_removed() {
+    let root = std::env::temp_dir().join(format!("pastebin-temp-{}", std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join(".tmp-abcd-1f"), b"trunc").unwrap();
+
+    let store = FileSystemStore::new(&root).unwrap();
+    assert!(!root.join(".tmp-abcd-1f").exists());
+    store.put("abcd", b"hello").unwrap();
+    assert_eq!(vec!["abcd".to_string()], store.list().unwrap());
+
+    fs::remove_dir_all(root).unwrap();
+  }
+}
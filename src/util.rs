@@ -1,102 +1,145 @@
 use crate::core::Record;
-use crate::{handle_err, loop_through_files_in_dir};
-use chrono::NaiveDate;
+use crate::handle_err;
+use crate::index::MetadataIndex;
+use crate::store::PasteStore;
 use chrono::{Duration, Utc};
 use r_cache::cache::Cache;
 use std::error::Error;
-use std::fs::{self, File, OpenOptions};
-use std::io::prelude::*;
-use std::io::BufReader;
+use std::fs;
+use std::io;
 use std::path::Path;
 
 pub const SIMPLE_DATE_FORMAT: &str = "%Y-%m-%d";
 
-pub async fn add_id_to_file_for_deletion(
-  id: String,
-  days_to_delete_after: i32,
-) -> Result<(), Box<dyn Error>> {
-  let (file_path_with_name, _) = get_deletion_file_name_with_path(days_to_delete_after + 1);
+/// ORPHAN_GRACE = 600 => seconds for 10 minutes. Uploads write their content before its
+/// record, so on a shared store content younger than that might belong to an upload another
+/// instance is still in the middle of.
+const ORPHAN_GRACE: i64 = 600;
 
-  if !Path::new(&file_path_with_name).exists() {
-    File::create(&file_path_with_name)?;
+/// Names of all the files in the directory and in its subdirectories (the sharded
+/// `upload/ab/cd/<id>` layout), hidden entries such as in-flight temp files are skipped.
+pub fn files_in_directory_tree<P: AsRef<Path>>(directory_name: P) -> io::Result<Vec<String>> {
+  let mut filenames = vec![];
+  let mut pending = vec![directory_name.as_ref().to_path_buf()];
+
+  while let Some(directory) = pending.pop() {
+    for entry in fs::read_dir(directory)? {
+      let dir_entry = entry?;
+      let filename = dir_entry.file_name().to_string_lossy().into_owned();
+      if filename.starts_with('.') {
+        continue;
+      }
+
+      if dir_entry.file_type()?.is_dir() {
+        pending.push(dir_entry.path());
+      } else {
+        filenames.push(filename);
+      }
+    }
   }
 
-  let mut file = OpenOptions::new()
-    .write(true)
-    .append(true)
-    .open(file_path_with_name)
-    .unwrap();
-  writeln!(file, "{}", id)?;
-  Ok(())
+  Ok(filenames)
 }
 
-pub fn get_deletion_file_name_with_path(days_to_add: i32) -> (String, String) {
-  let date = Utc::now()
-    .checked_add_signed(Duration::days(days_to_add as i64))
-    .unwrap();
-  (
-    format!(
-      "deletions/{file_name}.txt",
-      file_name = date.format(SIMPLE_DATE_FORMAT)
-    ),
-    date.format(SIMPLE_DATE_FORMAT).to_string(),
-  )
+pub async fn populate_cache_on_first_run(cache: &Cache<String, String>, index: &MetadataIndex) {
+  let records = index.live_records(Utc::now().timestamp());
+  handle_err!(
+    records,
+    "trying to load the live records from the metadata index",
+    {
+      return;
+    }
+  );
+
+  for r in records.unwrap() {
+    if !r.is_key_expired() {
+      cache
+        .set(
+          r.key.clone(),
+          "".to_string(),
+          Some(std::time::Duration::from_secs(
+            r.remaining_time_to_expiry() as u64
+          )),
+        )
+        .await;
+    }
+  }
 }
 
-pub fn loop_through_files_in_directory<F>(directory_name: &str, callback: F) -> u32
-where
-  F: Fn(String),
-{
+/// Deletes the stored pastes without a record in the metadata index (or in the store, for
+/// shared stores), left behind when the process died between writing the content and its
+/// metadata, the deduplicated blobs no paste got linked to and the records of shared stores
+/// whose content is gone. Returns the number deleted.
+pub fn remove_orphaned_pastes(
+  store: &dyn PasteStore,
+  index: &MetadataIndex,
+) -> Result<u32, Box<dyn Error>> {
+  let oldest_in_flight = Utc::now() - Duration::seconds(ORPHAN_GRACE);
   let mut counter: u32 = 0;
-  for entry in fs::read_dir(directory_name).unwrap() {
-    let dir_entry = entry.unwrap();
-    let filename = dir_entry.file_name().into_string().unwrap();
-    callback(filename);
+  for id in store.list()? {
+    if index.contains(&id)? || store.get_record(&id)?.is_some() {
+      continue;
+    }
+    if matches!(store.modified(&id)?, Some(modified) if modified > oldest_in_flight) {
+      continue;
+    }
+
+    store.delete(&id)?;
     counter += 1;
   }
 
-  counter
+  counter += store.remove_unlinked(oldest_in_flight)?;
+
+  // the content is written first and deleted first, a record without it is left over
+  for id in store.list_records()? {
+    if !store.exists(&id) {
+      store.delete(&id)?;
+      counter += 1;
+    }
+  }
+
+  Ok(counter)
 }
 
-pub async fn populate_cache_on_first_run(cache: &Cache<String, String>) {
-  let today = Utc::now().naive_utc().date();
-  loop_through_files_in_dir!("deletions", filename, {
-    let date = filename.split(".").collect::<Vec<&str>>()[0];
-
-    let parse_resp = NaiveDate::parse_from_str(date, SIMPLE_DATE_FORMAT);
-    handle_err!(parse_resp, "trying to parse the date from the deletions", {
-    });
-
-    let date = parse_resp.unwrap();
-
-    if date.ge(&today) {
-      let file_resp = std::fs::File::open("deletions/".to_string() + &filename);
-      handle_err!(
-        file_resp.as_ref(),
-        format!(
-          "trying to open the qualifying deletions file ({})",
-          filename
-        ),
-        {}
-      );
-
-      let reader = BufReader::new(file_resp.unwrap());
-
-      for line in reader.lines() {
-        let r = Record::from(line.expect("Something is not right with this line"));
-
-        if !r.is_key_expired() {
-          cache
-            .set(
-              r.key.clone(),
-              "".to_string(),
-              Some(std::time::Duration::from_secs(
-                r.remaining_time_to_expiry() as u64
-              )),
-            )
-            .await;
-        }
-      }
+/// Adds the record of a paste some other instance sharing the store uploaded to the index, so
+/// this instance serves and sweeps it too. Returns `None` for unknown or expired pastes, the
+/// expired ones get deleted on the way, whoever uploaded them might not be around anymore.
+pub fn adopt_shared_record(
+  store: &dyn PasteStore,
+  index: &MetadataIndex,
+  id: &str,
+) -> Result<Option<Record>, Box<dyn Error>> {
+  let record = match store.get_record(id)? {
+    Some(record) => record,
+    None => return Ok(None),
+  };
+
+  if record.is_key_expired() {
+    Record::delete_file(store, id)?;
+    return Ok(None);
+  }
+
+  record.log(index)?;
+
+  Ok(Some(record))
+}
+
+/// Adopts the records of every paste in a shared store that the index doesn't know about
+/// yet, see `adopt_shared_record`. Returns the number adopted.
+pub fn adopt_shared_records(
+  store: &dyn PasteStore,
+  index: &MetadataIndex,
+) -> Result<u32, Box<dyn Error>> {
+  if !store.is_shared() {
+    return Ok(0);
+  }
+
+  let mut counter: u32 = 0;
+  for id in store.list()? {
+    if !index.contains(&id)? && adopt_shared_record(store, index, &id)?.is_some() {
+      counter += 1;
     }
-  });
+  }
+
+  Ok(counter)
 }
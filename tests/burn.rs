@@ -0,0 +1,54 @@
+mod common;
+
+use common::{client, upload, upload_with_key};
+use rocket::http::Status;
+
+#[rocket::async_test]
+async fn test_head_does_not_burn_the_paste() {
+  let client = client().await;
+  let id = upload(&client, "/burn", "read once").await;
+
+  let response = client.head(format!("/{}", id)).dispatch().await;
+  assert_eq!(Status::Ok, response.status());
+
+  let response = client.get(format!("/{}", id)).dispatch().await;
+  assert_eq!(Some("read once".to_string()), response.into_string().await);
+
+  let response = client.get(format!("/{}", id)).dispatch().await;
+  assert_eq!(Status::Gone, response.status());
+}
+
+#[rocket::async_test]
+async fn test_burn_takes_the_upload_query() {
+  let client = client().await;
+
+  for route in [
+    "/burn?max_views=2",
+    "/burn/1h?idle=10m",
+    "/?burn&max_views=2",
+  ] {
+    let response = client.post(route).body("content").dispatch().await;
+    assert_eq!(Status::UnprocessableEntity, response.status(), "{}", route);
+  }
+
+  let envelope = r#"{"iv": "AAECAwQFBgcICQoL", "salt": "AAECAwQFBgc=",
+    "ciphertext": "AAECAwQFBgcICQoLDA0ODxAREhM=", "params": {"cipher": "aes-gcm",
+    "key_size": 256, "tag_size": 128, "kdf": "pbkdf2-sha256", "iterations": 100000}}"#;
+  let id = upload(&client, "/private?burn", envelope).await;
+  let response = client.get(format!("/{}", id)).dispatch().await;
+  assert_eq!(Status::Ok, response.status());
+  let response = client.get(format!("/{}", id)).dispatch().await;
+  assert_eq!(Status::Gone, response.status());
+}
+
+#[rocket::async_test]
+async fn test_burned_permanent_pastes_are_gone() {
+  let client = client().await;
+  let id = upload_with_key(&client, "/?permanent&burn", "read once").await;
+
+  let response = client.get(format!("/{}", id)).dispatch().await;
+  assert_eq!(Status::Ok, response.status());
+
+  let response = client.get(format!("/{}", id)).dispatch().await;
+  assert_eq!(Status::Gone, response.status());
+}
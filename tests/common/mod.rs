@@ -0,0 +1,48 @@
+// every test crate only uses some of the helpers
+#![allow(dead_code)]
+
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use rocket_pastebin::store::StorageBackend;
+use rocket_pastebin::{routes, CustomConfig};
+
+/// the one API key the test instances accept
+pub const API_KEY: &str = "s3cr3t";
+
+pub async fn client() -> Client {
+  let custom_config = CustomConfig {
+    storage: StorageBackend::Memory,
+    index_path: ":memory:".to_string(),
+    api_keys: vec![API_KEY.to_string()],
+    ..CustomConfig::new()
+  };
+
+  Client::tracked(routes::build(custom_config).await)
+    .await
+    .expect("valid rocket instance")
+}
+
+/// uploads the given body to the given upload route and returns the ID of the paste
+pub async fn upload(client: &Client, route: &str, body: &str) -> String {
+  id_of(client.post(route).body(body).dispatch().await).await
+}
+
+/// `upload` with `API_KEY`, for the routes and options that need one
+pub async fn upload_with_key(client: &Client, route: &str, body: &str) -> String {
+  let response = client
+    .post(route)
+    .header(Header::new("X-Api-Key", API_KEY))
+    .body(body)
+    .dispatch()
+    .await;
+  id_of(response).await
+}
+
+async fn id_of(response: LocalResponse<'_>) -> String {
+  assert_eq!(Status::Ok, response.status());
+
+  // `/private` uploads answer with the URL of the view page
+  let url = response.into_string().await.unwrap();
+  let url = url.strip_suffix("/view").unwrap_or(&url);
+  url.rsplit('/').next().unwrap().to_string()
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use common::{client, upload};
+
+#[rocket::async_test]
+async fn test_expiring_pastes_carry_their_expiry() {
+  let client = client().await;
+  let id = upload(&client, "/1h", "hello").await;
+
+  let response = client.get(format!("/{}", id)).dispatch().await;
+  let headers = response.headers();
+  let expires_at = headers.get_one("X-Paste-Expires-At").unwrap();
+  assert!(chrono::DateTime::parse_from_rfc3339(expires_at).is_ok());
+  assert!(headers.get_one("Expires").unwrap().ends_with(" GMT"));
+
+  let max_age: i64 = headers
+    .get_one("Cache-Control")
+    .and_then(|value| value.strip_prefix("max-age="))
+    .unwrap()
+    .parse()
+    .unwrap();
+  assert!((3_590..=3_600).contains(&max_age));
+}
+
+#[rocket::async_test]
+async fn test_pastes_limited_in_reads_are_not_cached() {
+  let client = client().await;
+
+  for route in ["/burn", "/?max_views=2", "/?idle=10m"] {
+    let id = upload(&client, route, "hello").await;
+
+    let response = client.get(format!("/{}", id)).dispatch().await;
+    let headers = response.headers();
+    assert!(headers.get_one("X-Paste-Expires-At").is_some(), "{}", route);
+    assert_eq!(
+      Some("no-store"),
+      headers.get_one("Cache-Control"),
+      "{}",
+      route
+    );
+    assert_eq!(None, headers.get_one("Expires"), "{}", route);
+  }
+}
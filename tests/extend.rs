@@ -0,0 +1,57 @@
+mod common;
+
+use common::{client, upload, API_KEY};
+use rocket::http::{Header, Status};
+
+#[rocket::async_test]
+async fn test_extend_needs_an_api_key() {
+  let client = client().await;
+  let id = upload(&client, "/", "hello").await;
+
+  let response = client.post(format!("/{}/extend/1d", id)).dispatch().await;
+  assert_eq!(Status::Unauthorized, response.status());
+
+  let response = client
+    .post(format!("/{}/extend/1d", id))
+    .header(Header::new("X-Api-Key", "wrong"))
+    .dispatch()
+    .await;
+  assert_eq!(Status::Forbidden, response.status());
+
+  let response = client
+    .post(format!("/{}/extend/1d", id))
+    .header(Header::new("Authorization", format!("Bearer {}", API_KEY)))
+    .dispatch()
+    .await;
+  assert_eq!(Status::Ok, response.status());
+}
+
+#[rocket::async_test]
+async fn test_extend_stays_within_the_maximum_retention() {
+  let client = client().await;
+  let id = upload(&client, "/", "hello").await;
+
+  // a week left already
+  let response = client
+    .post(format!("/{}/extend/30d", id))
+    .header(Header::new("X-Api-Key", API_KEY))
+    .dispatch()
+    .await;
+  assert_eq!(Status::UnprocessableEntity, response.status());
+}
+
+#[rocket::async_test]
+async fn test_burned_pastes_cannot_be_extended() {
+  let client = client().await;
+  let id = upload(&client, "/burn", "read once").await;
+
+  let response = client.get(format!("/{}", id)).dispatch().await;
+  assert_eq!(Status::Ok, response.status());
+
+  let response = client
+    .post(format!("/{}/extend/1d", id))
+    .header(Header::new("X-Api-Key", API_KEY))
+    .dispatch()
+    .await;
+  assert_eq!(Status::Gone, response.status());
+}
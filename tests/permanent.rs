@@ -0,0 +1,27 @@
+mod common;
+
+use common::{client, upload_with_key};
+use rocket::http::{Header, Status};
+
+#[rocket::async_test]
+async fn test_permanent_pastes_need_an_api_key() {
+  let client = client().await;
+
+  let response = client.post("/?permanent").body("forever").dispatch().await;
+  assert_eq!(Status::Unauthorized, response.status());
+
+  let response = client
+    .post("/?permanent")
+    .header(Header::new("Authorization", "Bearer wrong"))
+    .body("forever")
+    .dispatch()
+    .await;
+  assert_eq!(Status::Forbidden, response.status());
+
+  let id = upload_with_key(&client, "/?permanent", "forever").await;
+  let response = client.get(format!("/{}", id)).dispatch().await;
+  assert_eq!(
+    Some("never"),
+    response.headers().get_one("X-Paste-Expires-At")
+  );
+}
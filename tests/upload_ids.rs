@@ -0,0 +1,115 @@
+mod common;
+
+use common::{client, upload};
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::Client;
+use rocket_pastebin::quota::{QuotaPolicy, StorageQuota};
+use rocket_pastebin::store::StorageBackend;
+use rocket_pastebin::{routes, CustomConfig};
+use std::sync::Arc;
+
+#[rocket::async_test]
+async fn test_uploads_get_fresh_ids() {
+  let client = client().await;
+  let first = upload(&client, "/", "first").await;
+  let second = upload(&client, "/", "second").await;
+  assert_ne!(first, second);
+
+  let response = client.get(format!("/{}", first)).dispatch().await;
+  assert_eq!(Some("first".to_string()), response.into_string().await);
+}
+
+#[rocket::async_test]
+async fn test_spoofed_id_cannot_overwrite_a_paste() {
+  let client = client().await;
+  let id = upload(&client, "/", "original").await;
+
+  let response = client
+    .post("/")
+    .header(Header::new("unique-pastebin-id", id.clone()))
+    .body("overwritten")
+    .dispatch()
+    .await;
+  assert_eq!(Status::BadRequest, response.status());
+
+  let response = client
+    .post("/1h")
+    .header(Header::new("unique-pastebin-id", id.clone()))
+    .body("overwritten")
+    .dispatch()
+    .await;
+  assert_eq!(Status::BadRequest, response.status());
+
+  let response = client.get(format!("/{}", id)).dispatch().await;
+  assert_eq!(Some("original".to_string()), response.into_string().await);
+}
+
+#[rocket::async_test]
+async fn test_spoofed_cache_clearing_is_rejected() {
+  let client = client().await;
+
+  let response = client
+    .post("/")
+    .header(Header::new("time-to-clear-expired-keys", "yes"))
+    .body("hello")
+    .dispatch()
+    .await;
+  assert_eq!(Status::BadRequest, response.status());
+}
+
+#[rocket::async_test]
+async fn test_slugs() {
+  let client = client().await;
+
+  let response = client
+    .post("/?slug=deploy-checklist")
+    .body("original")
+    .dispatch()
+    .await;
+  assert_eq!(Status::Ok, response.status());
+  assert!(response
+    .into_string()
+    .await
+    .unwrap()
+    .ends_with("/deploy-checklist"));
+
+  let response = client
+    .post("/1h?slug=deploy-checklist")
+    .body("overwritten")
+    .dispatch()
+    .await;
+  assert_eq!(Status::Conflict, response.status());
+
+  let response = client.get("/deploy-checklist").dispatch().await;
+  assert_eq!(Some("original".to_string()), response.into_string().await);
+
+  let response = client.post("/?slug=stats").body("hello").dispatch().await;
+  assert_eq!(Status::UnprocessableEntity, response.status());
+}
+
+#[rocket::async_test]
+async fn test_failed_uploads_release_their_slug() {
+  let custom_config = CustomConfig {
+    storage: StorageBackend::Memory,
+    index_path: ":memory:".to_string(),
+    quota: Some(Arc::new(StorageQuota::new(16, QuotaPolicy::Reject))),
+    ..CustomConfig::new()
+  };
+  let client = Client::tracked(routes::build(custom_config).await)
+    .await
+    .expect("valid rocket instance");
+
+  let response = client
+    .post("/?slug=release-notes")
+    .body("far too long for the quota")
+    .dispatch()
+    .await;
+  assert_eq!(Status::InsufficientStorage, response.status());
+
+  let response = client
+    .post("/?slug=release-notes")
+    .body("short")
+    .dispatch()
+    .await;
+  assert_eq!(Status::Ok, response.status());
+}
@@ -0,0 +1,19 @@
+mod common;
+
+use common::{client, upload};
+use rocket::http::Status;
+
+#[rocket::async_test]
+async fn test_pastes_expire_after_their_last_view() {
+  let client = client().await;
+  let id = upload(&client, "/?max_views=2", "read twice").await;
+
+  for _ in 0..2 {
+    let response = client.get(format!("/{}", id)).dispatch().await;
+    assert_eq!(Status::Ok, response.status());
+    assert_eq!(Some("read twice".to_string()), response.into_string().await);
+  }
+
+  let response = client.get(format!("/{}", id)).dispatch().await;
+  assert_eq!(Status::Gone, response.status());
+}